@@ -0,0 +1,606 @@
+use std::f32::consts::PI;
+use std::str::FromStr;
+use crate::{Vec3, lights::luminance, sampler::{Sampler, sample_sphere}};
+
+//a direction picked by Bsdf::sample
+pub struct BsdfSample {
+    pub dir: Vec3,
+    //bsdf * cos / pdf, the throughput of the path is multiplied with it
+    pub weight: Vec3,
+    //per solid angle, 0 for specular samples
+    pub pdf: f32,
+    //picked from a delta lobe, like a mirror or refraction, that eval and pdf can never return
+    pub specular: bool,
+}
+
+//how a surface scatters light, incoming is the direction of the ray that hit it,
+//dir points away from the surface and normal is the normal of the hit
+pub trait Bsdf {
+    //picks the direction the ray continues in, None absorbs it
+    fn sample(&self, incoming: &Vec3, normal: &Vec3, sampler: &mut dyn Sampler) -> Option<BsdfSample>;
+    //bsdf * cos for light arriving from dir, 0 for specular reflections
+    fn eval(&self, incoming: &Vec3, normal: &Vec3, dir: &Vec3) -> Vec3;
+    //the density sample picks dir with
+    fn pdf(&self, incoming: &Vec3, normal: &Vec3, dir: &Vec3) -> f32;
+    //every lobe is a delta lobe, eval and pdf are always 0 and lights are not sampled directly
+    fn is_specular(&self) -> bool {
+        false
+    }
+}
+
+//mirror direction of incoming around the normal
+pub fn reflect(incoming: &Vec3, normal: &Vec3) -> Vec3 {
+    incoming - normal * (2. * incoming.dot(normal))
+}
+
+fn reflectance(cos: f32, eta: f32) -> f32 {
+    //Schlick's approximation
+    let r0 = ((1. - eta) / (1. + eta)).powi(2);
+    r0 + (1. - r0) * (1. - cos).powi(5)
+
+}
+
+//the eta is the ratio of the refractive indecies of the second medium and the first medium
+//index1*sin(theta1) = index2*sin(theta2)
+//None for total internal reflection
+fn refract(incoming: &Vec3, normal: &Vec3, eta: f32) -> Option<Vec3> {
+    let cos_theta = (-1. * incoming).dot(normal).min(1.);
+    //sin^2theta + cos^2theta = 1
+    if (eta * (1.- cos_theta * cos_theta).abs().sqrt()) > 1. {
+        return None
+    }
+    let r_out_perp: Vec3 = eta * &(incoming + normal * cos_theta);
+    let r_out_parallel: Vec3 = -(1.-r_out_perp.length_squared()).abs().sqrt() * normal;
+    Some((r_out_parallel + r_out_perp).normalize())
+}
+
+//u is uniform in 0-1 and decides between reflection and refraction
+fn snell(incoming: &Vec3, normal: &Vec3, eta: f32, u: f32) -> Vec3 {
+    let cos_theta = (-1. * incoming).dot(normal).min(1.);
+    if reflectance(cos_theta.abs(), eta) > u {
+        return reflect(incoming, normal).normalize()
+    }
+    refract(incoming, normal, eta).unwrap_or_else(|| reflect(incoming, normal).normalize())
+}
+
+//lambertian reflection
+pub struct Diffuse {
+    pub albedo: Vec3,
+}
+
+impl Bsdf for Diffuse {
+    fn sample(&self, incoming: &Vec3, normal: &Vec3, sampler: &mut dyn Sampler) -> Option<BsdfSample> {
+        //normal + a point of the unit sphere is cos distributed around the normal
+        let dir = (normal + sample_sphere(sampler.get_2d())).normalize();
+        let pdf = self.pdf(incoming, normal, &dir);
+        Some(BsdfSample { dir, weight: self.albedo.clone(), pdf, specular: false })
+    }
+
+    fn eval(&self, incoming: &Vec3, normal: &Vec3, dir: &Vec3) -> Vec3 {
+        &self.albedo * self.pdf(incoming, normal, dir)
+    }
+
+    fn pdf(&self, _incoming: &Vec3, normal: &Vec3, dir: &Vec3) -> f32 {
+        (normal.dot(dir) / PI).max(0.)
+    }
+}
+
+//the mirror direction moved by a random point of a sphere with radius roughness
+pub struct Metal {
+    pub albedo: Vec3,
+    //normalized
+    pub roughness: f32,
+}
+
+impl Bsdf for Metal {
+    fn sample(&self, incoming: &Vec3, normal: &Vec3, sampler: &mut dyn Sampler) -> Option<BsdfSample> {
+        let dir = reflect(incoming, normal).normalize() + sample_sphere(sampler.get_2d()) * self.roughness;
+        let dir = dir.normalize();
+        let pdf = self.pdf(incoming, normal, &dir);
+        //the rim of the sphere can round to outside of it
+        if pdf <= 0. && !self.is_specular() {
+            return None;
+        }
+        Some(BsdfSample { dir, weight: self.albedo.clone(), pdf, specular: self.is_specular() })
+    }
+
+    //the bsdf * cos is albedo * pdf, the weight of a sample is the albedo
+    fn eval(&self, incoming: &Vec3, normal: &Vec3, dir: &Vec3) -> Vec3 {
+        &self.albedo * self.pdf(incoming, normal, dir)
+    }
+
+    fn pdf(&self, incoming: &Vec3, normal: &Vec3, dir: &Vec3) -> f32 {
+        if self.is_specular() {
+            return 0.;
+        }
+        let cos = reflect(incoming, normal).normalize().dot(dir).clamp(-1., 1.);
+        let sin2 = 1. - cos * cos;
+        let rough2 = self.roughness * self.roughness;
+        if rough2 < sin2 || (self.roughness < 1. && cos <= 0.) {
+            return 0.;
+        }
+        //the ray through the sphere hits its surface at distances cos +- root
+        let root = (rough2 - sin2).max(1e-8).sqrt();
+        let near = cos - root;
+        let far = cos + root;
+        let mut area = far * far;
+        if near > 0. {
+            area += near * near;
+        }
+        area / (4. * PI * self.roughness * root)
+    }
+
+    fn is_specular(&self) -> bool {
+        self.roughness <= 0.
+    }
+}
+
+//reflects or refracts with the chance given by the fresnel equations
+pub struct Glass {
+    pub tint: Vec3,
+    //index of refraction
+    pub ior: f32,
+}
+
+impl Bsdf for Glass {
+    fn sample(&self, incoming: &Vec3, normal: &Vec3, sampler: &mut dyn Sampler) -> Option<BsdfSample> {
+        let dir = if incoming.dot(normal) > 0. {
+            //backface
+            snell(incoming, &(-1. * normal), self.ior, sampler.get_1d())
+        } else {
+            snell(incoming, normal, 1. / self.ior, sampler.get_1d())
+        };
+        assert!(dir.is_normalized(), "sample glass");
+        Some(BsdfSample { dir, weight: self.tint.clone(), pdf: 0., specular: true })
+    }
+
+    fn eval(&self, _incoming: &Vec3, _normal: &Vec3, _dir: &Vec3) -> Vec3 {
+        Vec3::default()
+    }
+
+    fn pdf(&self, _incoming: &Vec3, _normal: &Vec3, _dir: &Vec3) -> f32 {
+        0.
+    }
+
+    fn is_specular(&self) -> bool {
+        true
+    }
+}
+
+//metals with measured indices of refraction, eta and k at the red, green and blue wavelengths
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConductorKind {
+    Gold,
+    Copper,
+    Aluminium,
+    Silver,
+}
+
+impl ConductorKind {
+    pub const NAMES: [&'static str; 4] = ["gold", "copper", "aluminium", "silver"];
+
+    //the real part eta and the imaginary part k of the complex index of refraction
+    pub fn ior(&self) -> (Vec3, Vec3) {
+        match self {
+            Self::Gold => (Vec3::new(0.143, 0.374, 1.442), Vec3::new(3.983, 2.385, 1.603)),
+            Self::Copper => (Vec3::new(0.200, 0.924, 1.102), Vec3::new(3.912, 2.452, 2.142)),
+            Self::Aluminium => (Vec3::new(1.657, 0.880, 0.521), Vec3::new(9.224, 6.270, 4.837)),
+            Self::Silver => (Vec3::new(0.155, 0.117, 0.138), Vec3::new(4.828, 3.122, 2.147)),
+        }
+    }
+}
+
+impl FromStr for ConductorKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "gold" => Ok(Self::Gold),
+            "copper" => Ok(Self::Copper),
+            "aluminium" => Ok(Self::Aluminium),
+            "silver" => Ok(Self::Silver),
+            _ => Err(format!("unknown metal '{}', available metals: {}", s, Self::NAMES.join(", "))),
+        }
+    }
+}
+
+//fresnel reflectance of a conductor with the complex index of refraction eta + ik,
+//https://seblagarde.wordpress.com/2013/04/29/memo-on-fresnel-equations/
+fn fresnel_conductor(cos: f32, eta: f32, k: f32) -> f32 {
+    let cos = cos.clamp(0., 1.);
+    let cos2 = cos * cos;
+    let sin2 = 1. - cos2;
+    let t0 = eta * eta - k * k - sin2;
+    let a2b2 = (t0 * t0 + 4. * eta * eta * k * k).sqrt();
+    let t1 = a2b2 + cos2;
+    let a = (0.5 * (a2b2 + t0)).max(0.).sqrt();
+    let t2 = 2. * cos * a;
+    let rs = (t1 - t2) / (t1 + t2);
+    let t3 = cos2 * a2b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+    0.5 * (rp + rs)
+}
+
+//the direction towards the viewer and the normal on its side, for surfaces that reflect the same from both sides
+fn facing(incoming: &Vec3, normal: &Vec3) -> (Vec3, Vec3) {
+    let wo = -1. * incoming;
+    let n = if wo.dot(normal) < 0. { -1. * normal } else { normal.clone() };
+    (wo, n)
+}
+
+//the ggx (trowbridge-reitz) distribution of microfacet normals with smith shadowing,
+//https://jcgt.org/published/0007/04/01/ for sampling the visible normals
+#[derive(Clone, Copy)]
+struct Ggx {
+    //roughness squared
+    alpha: f32,
+}
+
+impl Ggx {
+    //density of microfacets with cos between their normal and the surface normal
+    fn distribution(&self, cos: f32) -> f32 {
+        let a2 = self.alpha * self.alpha;
+        let d = cos * cos * (a2 - 1.) + 1.;
+        a2 / (PI * d * d)
+    }
+
+    //smith lambda, the shadowed fraction of the microfacets seen at cos from the normal is lambda / (1 + lambda)
+    fn lambda(&self, cos: f32) -> f32 {
+        let tan2 = (1. - cos * cos).max(0.) / (cos * cos);
+        ((1. + self.alpha * self.alpha * tan2).sqrt() - 1.) / 2.
+    }
+
+    //the fraction of the microfacets visible from cos_o that light from cos_i reaches
+    fn shadowing(&self, cos_o: f32, cos_i: f32) -> f32 {
+        1. / (1. + self.lambda(cos_o) + self.lambda(cos_i))
+    }
+
+    //a microfacet normal seen from wo, in the frame where the surface normal is z
+    fn sample_visible_normal(&self, wo: &Vec3, u: (f32, f32)) -> Vec3 {
+        //stretch the view so the microfacets become a hemisphere
+        let v = Vec3::new(self.alpha * wo.x, self.alpha * wo.y, wo.z).normalize();
+        let len2 = v.x * v.x + v.y * v.y;
+        let t1 = if len2 > 0. { Vec3::new(-v.y, v.x, 0.) / len2.sqrt() } else { Vec3::new(1., 0., 0.) };
+        let t2 = v.cross(&t1);
+        //a point on the projected hemisphere, the half that is hidden behind the horizon is squashed
+        let r = u.0.sqrt();
+        let phi = 2. * PI * u.1;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1. + v.z);
+        let p2 = (1. - s) * (1. - p1 * p1).max(0.).sqrt() + s * r * phi.sin();
+        let h = t1 * p1 + t2 * p2 + v * (1. - p1 * p1 - p2 * p2).max(0.).sqrt();
+        Vec3::new(self.alpha * h.x, self.alpha * h.y, h.z.max(0.)).normalize()
+    }
+
+    //wo mirrored on a visible microfacet, may point below the surface
+    fn sample(&self, wo: &Vec3, n: &Vec3, u: (f32, f32)) -> Vec3 {
+        let (t, b) = n.orthonormal_basis();
+        let local = Vec3::new(wo.dot(&t), wo.dot(&b), wo.dot(n));
+        let m = self.sample_visible_normal(&local, u);
+        let m = (t * m.x + b * m.y + n * m.z).normalize();
+        (&m * (2. * wo.dot(&m)) - wo).normalize()
+    }
+
+    //bsdf * cos without the fresnel term, both directions have to be above the surface
+    fn eval(&self, wo: &Vec3, n: &Vec3, dir: &Vec3) -> f32 {
+        let (cos_o, cos_i) = (wo.dot(n), dir.dot(n));
+        let m = (wo + dir).normalize();
+        self.distribution(m.dot(n)) * self.shadowing(cos_o, cos_i) / (4. * cos_o)
+    }
+
+    //the density sample picks dir with, both directions have to be above the surface
+    fn pdf(&self, wo: &Vec3, n: &Vec3, dir: &Vec3) -> f32 {
+        //visible normal density over the jacobian of the reflection, 4 * dot(wo, m)
+        let cos_o = wo.dot(n);
+        let m = (wo + dir).normalize();
+        self.distribution(m.dot(n)) / ((1. + self.lambda(cos_o)) * 4. * cos_o)
+    }
+}
+
+//ggx roughness, 0 stays a perfect mirror and the rest is kept away from it for the precision of f32
+fn alpha(roughness: f32) -> f32 {
+    if roughness <= 0. { 0. } else { (roughness * roughness).max(1e-3) }
+}
+
+//microfacet metal
+pub struct Conductor {
+    //multiplies the fresnel reflectance
+    pub tint: Vec3,
+    pub eta: Vec3,
+    pub k: Vec3,
+    //roughness squared, 0 is a perfect mirror
+    pub alpha: f32,
+}
+
+impl Conductor {
+    pub fn new(tint: Vec3, roughness: f32, eta: Vec3, k: Vec3) -> Self {
+        Self { tint, eta, k, alpha: alpha(roughness) }
+    }
+
+    fn fresnel(&self, cos: f32) -> Vec3 {
+        let f = Vec3::new(
+            fresnel_conductor(cos, self.eta.x, self.k.x),
+            fresnel_conductor(cos, self.eta.y, self.k.y),
+            fresnel_conductor(cos, self.eta.z, self.k.z),
+        );
+        &self.tint * f
+    }
+}
+
+impl Bsdf for Conductor {
+    fn sample(&self, incoming: &Vec3, normal: &Vec3, sampler: &mut dyn Sampler) -> Option<BsdfSample> {
+        let (wo, n) = facing(incoming, normal);
+        if self.is_specular() {
+            return Some(BsdfSample { dir: reflect(incoming, &n).normalize(), weight: self.fresnel(wo.dot(&n)), pdf: 0., specular: true });
+        }
+        let ggx = Ggx { alpha: self.alpha };
+        let dir = ggx.sample(&wo, &n, sampler.get_2d());
+        let (cos_o, cos_i) = (wo.dot(&n), dir.dot(&n));
+        if cos_o <= 0. || cos_i <= 0. {
+            return None;
+        }
+        //bsdf * cos / pdf, the distribution cancels
+        let m = (&wo + &dir).normalize();
+        let weight = self.fresnel(wo.dot(&m)) * (ggx.shadowing(cos_o, cos_i) * (1. + ggx.lambda(cos_o)));
+        let pdf = ggx.pdf(&wo, &n, &dir);
+        Some(BsdfSample { dir, weight, pdf, specular: false })
+    }
+
+    fn eval(&self, incoming: &Vec3, normal: &Vec3, dir: &Vec3) -> Vec3 {
+        let (wo, n) = facing(incoming, normal);
+        if self.is_specular() || wo.dot(&n) <= 0. || dir.dot(&n) <= 0. {
+            return Vec3::default();
+        }
+        let m = (&wo + dir).normalize();
+        self.fresnel(wo.dot(&m)) * Ggx { alpha: self.alpha }.eval(&wo, &n, dir)
+    }
+
+    fn pdf(&self, incoming: &Vec3, normal: &Vec3, dir: &Vec3) -> f32 {
+        let (wo, n) = facing(incoming, normal);
+        if self.is_specular() || wo.dot(&n) <= 0. || dir.dot(&n) <= 0. {
+            return 0.;
+        }
+        Ggx { alpha: self.alpha }.pdf(&wo, &n, dir)
+    }
+
+    fn is_specular(&self) -> bool {
+        self.alpha <= 0.
+    }
+}
+
+//schlick's approximation with a reflectance per color channel
+fn schlick(f0: &Vec3, cos: f32) -> Vec3 {
+    let t = (1. - cos.clamp(0., 1.)).powi(5);
+    f0 + (Vec3::new1(1.) - f0) * t
+}
+
+//ggx roughness of the clearcoat, it is always glossy
+const CLEARCOAT_ALPHA: f32 = 0.05;
+
+//the disney principled bsdf, https://media.disneyanimation.com/uploads/production/publication_asset/48/asset/s2012_pbs_disney_brdf_notes_v3.pdf
+//a diffuse base with sheen, a ggx specular layer, a clearcoat on top and smooth transmission,
+//every parameter but ior is in 0-1
+pub struct Principled {
+    pub base: Vec3,
+    pub metallic: f32,
+    pub roughness: f32,
+    //reflectance of the dielectric at normal incidence, 0.5 is 4%
+    pub specular: f32,
+    pub clearcoat: f32,
+    pub sheen: f32,
+    pub transmission: f32,
+    pub ior: f32,
+}
+
+impl Principled {
+    fn ggx(&self) -> Ggx {
+        Ggx { alpha: alpha(self.roughness.max(0.01)) }
+    }
+
+    //the reflectance of the specular layer at normal incidence, colored by the base for metals
+    fn f0(&self) -> Vec3 {
+        Vec3::new1(0.08 * self.specular).lerp(&self.base, self.metallic)
+    }
+
+    //the chances of sampling the diffuse, specular, clearcoat and transmission lobes, roughly their share of the reflected light
+    fn lobes(&self, cos_o: f32) -> [f32; 4] {
+        let weights = [
+            (1. - self.metallic) * (1. - self.transmission),
+            luminance(&schlick(&self.f0(), cos_o)),
+            0.25 * self.clearcoat * schlick(&Vec3::new1(0.04), cos_o).x,
+            (1. - self.metallic) * self.transmission,
+        ];
+        let total: f32 = weights.iter().sum();
+        if total <= 0. {
+            return [0.; 4];
+        }
+        weights.map(|w| w / total)
+    }
+
+    //the glass part, only refracts, the reflection of the dielectric comes from the specular layer
+    fn sample_transmission(&self, incoming: &Vec3, normal: &Vec3, chance: f32) -> BsdfSample {
+        let (n, eta) = if incoming.dot(normal) > 0. { (-1. * normal, self.ior) } else { (normal.clone(), 1. / self.ior) };
+        let cos = (-1. * incoming).dot(&n);
+        let weight = &self.base * ((1. - self.metallic) * self.transmission / chance);
+        match refract(incoming, &n, eta) {
+            Some(dir) => BsdfSample { dir, weight: weight * (1. - reflectance(cos, eta)), pdf: 0., specular: true },
+            None => BsdfSample { dir: reflect(incoming, &n).normalize(), weight, pdf: 0., specular: true },
+        }
+    }
+}
+
+impl Bsdf for Principled {
+    fn sample(&self, incoming: &Vec3, normal: &Vec3, sampler: &mut dyn Sampler) -> Option<BsdfSample> {
+        let (wo, n) = facing(incoming, normal);
+        let lobes = self.lobes(wo.dot(&n));
+        //the last lobe with a weight takes what rounding leaves of u, a lobe without one is never picked
+        let last = lobes.iter().rposition(|w| *w > 0.)?;
+        let mut u = sampler.get_1d();
+        let mut lobe = 0;
+        while lobe < last && u >= lobes[lobe] {
+            u -= lobes[lobe];
+            lobe += 1;
+        }
+        let dir = match lobe {
+            0 => (&n + sample_sphere(sampler.get_2d())).normalize(),
+            1 => self.ggx().sample(&wo, &n, sampler.get_2d()),
+            2 => Ggx { alpha: CLEARCOAT_ALPHA }.sample(&wo, &n, sampler.get_2d()),
+            _ => return Some(self.sample_transmission(incoming, normal, lobes[3])),
+        };
+        let pdf = self.pdf(incoming, normal, &dir);
+        if pdf <= 0. {
+            return None;
+        }
+        let weight = self.eval(incoming, normal, &dir) / pdf;
+        Some(BsdfSample { dir, weight, pdf, specular: false })
+    }
+
+    fn eval(&self, incoming: &Vec3, normal: &Vec3, dir: &Vec3) -> Vec3 {
+        let (wo, n) = facing(incoming, normal);
+        let (cos_o, cos_i) = (wo.dot(&n), dir.dot(&n));
+        if cos_o <= 0. || cos_i <= 0. {
+            return Vec3::default();
+        }
+        let m = (&wo + dir).normalize();
+        let cos_d = dir.dot(&m);
+        //diffuse with the retro-reflection of rough surfaces at grazing angles
+        let fd90 = 0.5 + 2. * self.roughness * cos_d * cos_d;
+        let fd = (1. + (fd90 - 1.) * (1. - cos_o).powi(5)) * (1. + (fd90 - 1.) * (1. - cos_i).powi(5));
+        let diffuse = &self.base * (fd * cos_i / PI);
+        //sheen is tinted halfway towards the hue of the base
+        let lum = luminance(&self.base);
+        let tint = if lum > 0. { &self.base / lum } else { Vec3::new1(1.) };
+        let sheen = Vec3::new1(1.).lerp(&tint, 0.5) * (self.sheen * (1. - cos_d).powi(5) * cos_i);
+        let specular = schlick(&self.f0(), wo.dot(&m)) * self.ggx().eval(&wo, &n, dir);
+        let clearcoat = 0.25 * self.clearcoat * schlick(&Vec3::new1(0.04), wo.dot(&m)).x * Ggx { alpha: CLEARCOAT_ALPHA }.eval(&wo, &n, dir);
+        (diffuse + sheen) * ((1. - self.metallic) * (1. - self.transmission)) + specular + Vec3::new1(clearcoat)
+    }
+
+    fn pdf(&self, incoming: &Vec3, normal: &Vec3, dir: &Vec3) -> f32 {
+        let (wo, n) = facing(incoming, normal);
+        let (cos_o, cos_i) = (wo.dot(&n), dir.dot(&n));
+        if cos_o <= 0. || cos_i <= 0. {
+            return 0.;
+        }
+        let lobes = self.lobes(cos_o);
+        lobes[0] * cos_i / PI + lobes[1] * self.ggx().pdf(&wo, &n, dir) + lobes[2] * Ggx { alpha: CLEARCOAT_ALPHA }.pdf(&wo, &n, dir)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::SamplerKind;
+
+    //hands out the same 1d number every time, 2d samples are the middle of the square
+    struct Fixed(f32);
+
+    impl Sampler for Fixed {
+        fn start_sample(&mut self, _x: usize, _y: usize, _index: u32) {}
+        fn get_1d(&mut self) -> f32 {
+            self.0
+        }
+        fn get_2d(&mut self) -> (f32, f32) {
+            (0.5, 0.5)
+        }
+    }
+
+    //the light the bsdf reflects from a uniform white sky, checking every sample against eval and pdf on the way
+    //and that pdf is the density the samples really have
+    fn albedo(bsdf: &dyn Bsdf, incoming: &Vec3) -> Vec3 {
+        let normal = Vec3::up();
+        let n = 40000;
+        let mut sampler = SamplerKind::Independent.create(n, 1);
+        let (mut sum, mut scattered) = (Vec3::default(), 0);
+        for i in 0..n {
+            sampler.start_sample(0, 0, i);
+            let Some(s) = bsdf.sample(incoming, &normal, sampler.as_mut()) else {
+                continue;
+            };
+            sum = sum + &s.weight;
+            if s.specular {
+                continue;
+            }
+            scattered += 1;
+            let pdf = bsdf.pdf(incoming, &normal, &s.dir);
+            assert!((s.pdf - pdf).abs() <= 1e-3 * pdf, "sampled with pdf {} but pdf gives {}", s.pdf, pdf);
+            let weight = bsdf.eval(incoming, &normal, &s.dir) / pdf;
+            assert!((&s.weight - &weight).length() <= 1e-3 * weight.length().max(1.), "sampled with weight {:?} but eval / pdf is {:?}", s.weight, weight);
+        }
+        //the pdf integrated over the sphere on a grid is the share of samples that were not lost or specular
+        //fine, the density of a rough metal grows without bound at the rim of its cone
+        let grid = 1000;
+        let mut integral = 0.;
+        for i in 0..grid {
+            for j in 0..grid {
+                let dir = sample_sphere(((i as f32 + 0.5) / grid as f32, (j as f32 + 0.5) / grid as f32));
+                integral += bsdf.pdf(incoming, &normal, &dir) * 4. * PI / (grid * grid) as f32;
+            }
+        }
+        let share = scattered as f32 / n as f32;
+        assert!((integral - share).abs() < 0.02, "pdf integrates to {} but {} of the samples scattered", integral, share);
+        sum / n as f32
+    }
+
+    fn principled(metallic: f32, transmission: f32) -> Principled {
+        Principled { base: Vec3::new1(0.8), metallic, roughness: 0.3, specular: 0.5, clearcoat: 0., sheen: 0., transmission, ior: 1.5 }
+    }
+
+    #[test]
+    fn principled_lobes_without_weight_are_never_picked() {
+        let (incoming, normal) = (Vec3::new(0., 0., -1.), Vec3::up());
+        //the largest number below 1, what is left of it after the other lobes can round to more than the last weight
+        let u = 1. - f32::EPSILON / 2.;
+        for i in 0..=100 {
+            let metallic = i as f32 / 100.;
+            let sample = principled(metallic, 0.).sample(&incoming, &normal, &mut Fixed(u));
+            assert!(sample.is_some(), "no sample with metallic {}", metallic);
+            let sample = principled(0., metallic).sample(&incoming, &normal, &mut Fixed(u));
+            assert!(sample.is_some_and(|s| s.weight.x.is_finite()), "no sample with transmission {}", metallic);
+        }
+    }
+
+    #[test]
+    fn diffuse_and_metal_samples_match_eval_and_pdf() {
+        let incoming = Vec3::new(0.6, 0., -0.8);
+        let color = albedo(&Diffuse { albedo: Vec3::new1(0.5) }, &incoming);
+        assert!((color.x - 0.5).abs() < 1e-3, "diffuse reflects {:?} instead of 0.5", color);
+        for roughness in [0.2, 0.6, 1.] {
+            let color = albedo(&Metal { albedo: Vec3::new1(0.8), roughness }, &incoming);
+            assert!(color.x <= 0.8 + 1e-3, "metal with roughness {} reflects {:?}, more than its albedo", roughness, color);
+        }
+    }
+
+    #[test]
+    fn conductors_reflect_their_fresnel() {
+        let (eta, k) = ConductorKind::Gold.ior();
+        //a mirror looked at straight on reflects ((eta - 1)^2 + k^2) / ((eta + 1)^2 + k^2)
+        let mirror = Conductor::new(Vec3::new1(1.), 0., eta.clone(), k.clone());
+        let sample = mirror.sample(&Vec3::new(0., 0., -1.), &Vec3::up(), &mut Fixed(0.5)).unwrap();
+        let f = |eta: f32, k: f32| ((eta - 1.).powi(2) + k * k) / ((eta + 1.).powi(2) + k * k);
+        let expected = Vec3::new(f(eta.x, k.x), f(eta.y, k.y), f(eta.z, k.z));
+        assert!(sample.specular && (&sample.weight - &expected).length() < 1e-4, "gold reflects {:?} instead of {:?}", sample.weight, expected);
+        //rougher gold loses more light to shadowing between its microfacets, but never adds any
+        let mut last = expected;
+        for roughness in [0.1, 0.5, 1.] {
+            let color = albedo(&Conductor::new(Vec3::new1(1.), roughness, eta.clone(), k.clone()), &Vec3::new(0.6, 0., -0.8));
+            assert!(color.x <= last.x * 1.02 && color.z <= last.z * 1.02, "gold with roughness {} reflects {:?}", roughness, color);
+            last = color;
+        }
+    }
+
+    #[test]
+    fn principled_samples_match_eval_and_pdf() {
+        let incoming = Vec3::new(0.6, 0., -0.8);
+        let layered = Principled { clearcoat: 1., sheen: 0.5, ..principled(0.3, 0.) };
+        let color = albedo(&layered, &incoming);
+        assert!(color.x > 0.5 && color.x < 1., "the layered material reflects {:?}", color);
+        //a white metal only keeps what the microfacets shadow
+        let color = albedo(&Principled { base: Vec3::new1(1.), ..principled(1., 0.) }, &incoming);
+        assert!(color.x > 0.8 && color.x <= 1., "white metal reflects {:?}", color);
+        //transmission is a delta lobe and is left out of pdf, the rest still matches
+        let color = albedo(&principled(0., 1.), &incoming);
+        assert!(color.x > 0.5 && color.x <= 1., "clear glass lets through {:?}", color);
+    }
+}
@@ -0,0 +1,330 @@
+use crate::{Vec3, Object, geometry::{HitInfo, Ray}, objects::{MIN_HIT_DIST, MAX_HIT_DIST}};
+
+//https://pbr-book.org/3ed-2018/Primitives_and_Intersection_Acceleration/Bounding_Volume_Hierarchies
+const BIN_COUNT: usize = 12;
+const MAX_LEAF_SIZE: usize = 4;
+//cost of visiting a node relative to one primitive intersection
+const TRAVERSAL_COST: f32 = 0.125;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    pub fn empty() -> Self {
+        Self { min: Vec3::new1(f32::INFINITY), max: Vec3::new1(f32::NEG_INFINITY) }
+    }
+
+    pub fn from_points(points: &[Vec3]) -> Self {
+        points.iter().fold(Self::empty(), |acc, p| acc.grow(p))
+    }
+
+    pub fn grow(&self, p: &Vec3) -> Self {
+        Self {
+            min: Vec3::new(self.min.x.min(p.x), self.min.y.min(p.y), self.min.z.min(p.z)),
+            max: Vec3::new(self.max.x.max(p.x), self.max.y.max(p.y), self.max.z.max(p.z)),
+        }
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        self.grow(&other.min).grow(&other.max)
+    }
+
+    pub fn pad(&self, amount: f32) -> Self {
+        Self { min: &self.min - Vec3::new1(amount), max: &self.max + Vec3::new1(amount) }
+    }
+
+    pub fn centroid(&self) -> Vec3 {
+        (&self.min + &self.max) / 2.
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let d = &self.max - &self.min;
+        if d.x < 0. || d.y < 0. || d.z < 0. {
+            return 0.;
+        }
+        2. * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
+    //distance along the ray where it enters the box, None if it misses or enters after t_max
+    pub fn hit(&self, ray: &Ray, inv_dir: &Vec3, t_max: f32) -> Option<f32> {
+        let tx0 = (self.min.x - ray.start.x) * inv_dir.x;
+        let tx1 = (self.max.x - ray.start.x) * inv_dir.x;
+        let ty0 = (self.min.y - ray.start.y) * inv_dir.y;
+        let ty1 = (self.max.y - ray.start.y) * inv_dir.y;
+        let tz0 = (self.min.z - ray.start.z) * inv_dir.z;
+        let tz1 = (self.max.z - ray.start.z) * inv_dir.z;
+        let near = tx0.min(tx1).max(ty0.min(ty1)).max(tz0.min(tz1)).max(0.);
+        let far = tx0.max(tx1).min(ty0.max(ty1)).min(tz0.max(tz1)).min(t_max);
+        if near <= far {
+            Some(near)
+        } else {
+            None
+        }
+    }
+}
+
+fn axis(v: &Vec3, axis: usize) -> f32 {
+    match axis {
+        0 => v.x,
+        1 => v.y,
+        _ => v.z,
+    }
+}
+
+enum Node {
+    Leaf { bounds: Aabb, start: usize, count: usize },
+    Inner { bounds: Aabb, left: usize, right: usize },
+}
+
+impl Node {
+    fn bounds(&self) -> &Aabb {
+        match self {
+            Self::Leaf { bounds, .. } | Self::Inner { bounds, .. } => bounds,
+        }
+    }
+}
+
+pub struct Bvh {
+    nodes: Vec<Node>,
+    objects: Vec<Object>,
+    //planes and other infinite objects are tested on every ray
+    unbounded: Vec<Object>,
+}
+
+struct Prim {
+    bounds: Aabb,
+    centroid: Vec3,
+    obj: Object,
+}
+
+impl Bvh {
+    pub fn new(objects: Vec<Object>) -> Self {
+        let mut prims: Vec<Prim> = vec![];
+        let mut unbounded: Vec<Object> = vec![];
+        Self::flatten(objects, &mut prims, &mut unbounded);
+        let mut bvh = Self { nodes: vec![], objects: vec![], unbounded };
+        if !prims.is_empty() {
+            bvh.build(&mut prims, 0);
+            bvh.objects = prims.into_iter().map(|p| p.obj).collect();
+        }
+        bvh
+    }
+
+    //hand placed bounding boxes are dissolved, the hierarchy replaces them
+    fn flatten(objects: Vec<Object>, prims: &mut Vec<Prim>, unbounded: &mut Vec<Object>) {
+        for obj in objects {
+            match obj {
+                Object::BoundBox { inside, .. } => Self::flatten(inside, prims, unbounded),
+                obj => match obj.bounds() {
+                    Some(bounds) => prims.push(Prim { centroid: bounds.centroid(), bounds, obj }),
+                    None => unbounded.push(obj),
+                },
+            }
+        }
+    }
+
+    pub fn objects(&self) -> impl Iterator<Item = &Object> {
+        self.objects.iter().chain(self.unbounded.iter())
+    }
+
+    pub fn bounds(&self) -> Option<Aabb> {
+        if !self.unbounded.is_empty() {
+            return None;
+        }
+        self.nodes.first().map(|n| n.bounds().clone())
+    }
+
+    //builds the subtree of prims, which start at offset in the final object list, returns its node index
+    fn build(&mut self, prims: &mut [Prim], offset: usize) -> usize {
+        let bounds = prims.iter().fold(Aabb::empty(), |acc, p| acc.union(&p.bounds));
+        let index = self.nodes.len();
+        self.nodes.push(Node::Leaf { bounds: bounds.clone(), start: offset, count: prims.len() });
+        if prims.len() <= 2 {
+            return index;
+        }
+
+        let centroids = prims.iter().fold(Aabb::empty(), |acc, p| acc.grow(&p.centroid));
+        let extent = &centroids.max - &centroids.min;
+        let ax = if extent.x >= extent.y && extent.x >= extent.z { 0 } else if extent.y >= extent.z { 1 } else { 2 };
+        let (lo, span) = (axis(&centroids.min, ax), axis(&extent, ax));
+        if span <= 0. {
+            if prims.len() <= MAX_LEAF_SIZE {
+                return index;
+            }
+            //every centroid is in the same spot, the only option left is splitting the list
+            let mid = prims.len() / 2;
+            self.split(index, bounds, prims, mid, offset);
+            return index;
+        }
+
+        let bin_of = |p: &Prim| (((axis(&p.centroid, ax) - lo) / span * BIN_COUNT as f32) as usize).min(BIN_COUNT - 1);
+        let mut bin_bounds: Vec<Aabb> = vec![Aabb::empty(); BIN_COUNT];
+        let mut bin_counts = [0_usize; BIN_COUNT];
+        for p in prims.iter() {
+            let b = bin_of(p);
+            bin_counts[b] += 1;
+            bin_bounds[b] = bin_bounds[b].union(&p.bounds);
+        }
+
+        //surface area heuristic: the chance of hitting a child is proportional to its surface
+        let parent_area = bounds.surface_area().max(f32::EPSILON);
+        let mut best: Option<(usize, f32)> = None;
+        for split in 1..BIN_COUNT {
+            let (mut left, mut right) = (Aabb::empty(), Aabb::empty());
+            let (mut left_count, mut right_count) = (0, 0);
+            for b in 0..split {
+                left = left.union(&bin_bounds[b]);
+                left_count += bin_counts[b];
+            }
+            for b in split..BIN_COUNT {
+                right = right.union(&bin_bounds[b]);
+                right_count += bin_counts[b];
+            }
+            if left_count == 0 || right_count == 0 {
+                continue;
+            }
+            let cost = TRAVERSAL_COST + (left.surface_area() * left_count as f32 + right.surface_area() * right_count as f32) / parent_area;
+            if best.is_none_or(|(_, c)| cost < c) {
+                best = Some((split, cost));
+            }
+        }
+
+        match best {
+            Some((_, cost)) if cost >= prims.len() as f32 && prims.len() <= MAX_LEAF_SIZE => index,
+            Some((split, _)) => {
+                let mid = partition(prims, |p| bin_of(p) < split);
+                self.split(index, bounds, prims, mid, offset);
+                index
+            }
+            None => index,
+        }
+    }
+
+    fn split(&mut self, index: usize, bounds: Aabb, prims: &mut [Prim], mid: usize, offset: usize) {
+        let (left_prims, right_prims) = prims.split_at_mut(mid);
+        let left = self.build(left_prims, offset);
+        let right = self.build(right_prims, offset + mid);
+        self.nodes[index] = Node::Inner { bounds, left, right };
+    }
+
+    pub fn hit(&self, ray: &Ray) -> Option<HitInfo> {
+        let mut closest: Option<HitInfo> = Object::hit_all(ray, &self.unbounded);
+        //the object of the closest hit when it is in the tree, its light is filled in at the end
+        let mut closest_obj: Option<&Object> = None;
+        let mut min_dist = closest.as_ref().map_or(MAX_HIT_DIST, |h| (&h.p - &ray.start).length());
+        if self.nodes.is_empty() {
+            return closest;
+        }
+        let inv_dir = Vec3::new(1. / ray.dir.x, 1. / ray.dir.y, 1. / ray.dir.z);
+        let mut stack: Vec<(usize, f32)> = vec![];
+        if let Some(t) = self.nodes[0].bounds().hit(ray, &inv_dir, min_dist) {
+            stack.push((0, t));
+        }
+        while let Some((index, t)) = stack.pop() {
+            //something closer was found since this node was pushed
+            if t > min_dist {
+                continue;
+            }
+            match &self.nodes[index] {
+                Node::Leaf { start, count, .. } => {
+                    for obj in &self.objects[*start..*start + *count] {
+                        if let Some(hit) = obj.intersect(ray) {
+                            let len = (&hit.p - &ray.start).length();
+                            if len >= MIN_HIT_DIST && len < min_dist {
+                                min_dist = len;
+                                closest = Some(hit);
+                                closest_obj = Some(obj);
+                            }
+                        }
+                    }
+                }
+                Node::Inner { left, right, .. } => {
+                    let t_left = self.nodes[*left].bounds().hit(ray, &inv_dir, min_dist);
+                    let t_right = self.nodes[*right].bounds().hit(ray, &inv_dir, min_dist);
+                    //the nearer child is pushed last so it gets visited first
+                    match (t_left, t_right) {
+                        (Some(tl), Some(tr)) if tl <= tr => {
+                            stack.push((*right, tr));
+                            stack.push((*left, tl));
+                        }
+                        (Some(tl), Some(tr)) => {
+                            stack.push((*left, tl));
+                            stack.push((*right, tr));
+                        }
+                        (Some(tl), None) => stack.push((*left, tl)),
+                        (None, Some(tr)) => stack.push((*right, tr)),
+                        (None, None) => {}
+                    }
+                }
+            }
+        }
+        match closest_obj {
+            Some(obj) => closest.map(|hit| obj.with_light(hit)),
+            None => closest,
+        }
+    }
+}
+
+//moves every prim matching pred to the front, returns how many matched
+fn partition(prims: &mut [Prim], pred: impl Fn(&Prim) -> bool) -> usize {
+    let mut mid = 0;
+    for i in 0..prims.len() {
+        if pred(&prims[i]) {
+            prims.swap(i, mid);
+            mid += 1;
+        }
+    }
+    mid
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+    use crate::{geometry::{Material, Ray}, objects::QuadType};
+    use super::*;
+
+    //the same random spheres and quads for the same seed
+    fn objects(seed: u64) -> Vec<Object> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..200).map(|i| {
+            let pos = Vec3::random(&mut rng) * 20.;
+            if i % 2 == 0 {
+                Object::Sphere { pos, rad: rng.gen_range(0.1..2.), mat: Material::default() }
+            } else {
+                Object::new_quad(pos, Vec3::random(&mut rng) * 3., Vec3::random(&mut rng) * 3., QuadType::Rect(), Material::default())
+            }
+        }).collect()
+    }
+
+    #[test]
+    fn hits_match_a_linear_scan() {
+        let linear = objects(7);
+        let bvh = vec![Object::new_bvh(objects(7))];
+        let mut rng = StdRng::seed_from_u64(8);
+        let mut hits = 0;
+        for _ in 0..2000 {
+            //from anywhere around the objects towards their middle
+            let start = Vec3::random(&mut rng) * 30.;
+            let dir = (Vec3::random(&mut rng) * 10. - &start).normalize();
+            let ray = Ray::new(start, dir);
+            let expected = Object::hit_all(&ray, &linear).map(|h| h.p);
+            let found = Object::hit_all(&ray, &bvh).map(|h| h.p);
+            match (expected, found) {
+                (None, None) => {}
+                (Some(a), Some(b)) => {
+                    assert!((&a - &b).length() < 1e-4, "the bvh hit {:?} instead of {:?}", b, a);
+                    hits += 1;
+                }
+                (a, b) => panic!("the bvh hit {:?} but a linear scan {:?}", b, a),
+            }
+        }
+        assert!(hits > 200, "only {} rays hit something", hits);
+    }
+}
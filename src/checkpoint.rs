@@ -0,0 +1,233 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, Write};
+use std::path::Path;
+use crate::{Adaptive, Vec3, medium::{Atmosphere, Medium}, render::{Frame, PixelStats}, sampler::SamplerKind};
+
+const MAGIC: &[u8; 8] = b"RTCHECK2";
+//the largest width and height a checkpoint can have, keeps a broken header from allocating too much
+const MAX_SIDE: usize = 1 << 16;
+//bytes of count, sum, mean and m2
+const PIXEL_SIZE: u64 = 24;
+
+//everything needed to continue a render: the samples of every pixel, where the sample
+//sequence came from and the settings that change what a sample estimates, the samplers have no state besides the seed
+//the stratified sampler spreads its samples for the old sample count, resuming it to a higher count
+//is still correct but not stratified as well
+#[derive(Clone)]
+pub struct Checkpoint {
+    //the built-in scene or the scene file the render is of, a resume has to render the same one
+    pub scene: String,
+    //source_hash of the scene file, 0 for a built-in scene
+    pub scene_hash: u64,
+    pub sample_count: u32,
+    pub seed: u64,
+    pub sampler: SamplerKind,
+    pub spectral: bool,
+    pub bounce_count: u8,
+    pub min_bounce_count: u8,
+    pub adaptive: Option<Adaptive>,
+    pub atmosphere: Option<Atmosphere>,
+    pub frame: Frame,
+}
+
+//fnv-1a, unlike the std hasher it stays the same between rust versions
+pub fn source_hash(src: &[u8]) -> u64 {
+    src.iter().fold(0xcbf29ce484222325, |hash, b| (hash ^ *b as u64).wrapping_mul(0x100000001b3))
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn write_name(w: &mut impl Write, name: &str) -> io::Result<()> {
+    w.write_all(&(name.len() as u32).to_le_bytes())?;
+    w.write_all(name.as_bytes())
+}
+
+fn read_name(r: &mut impl Read, max_len: usize, what: &str) -> io::Result<String> {
+    let len = read_u32(r)? as usize;
+    if len > max_len {
+        return Err(invalid(format!("the {} is too long", what)));
+    }
+    let mut name = vec![0; len];
+    r.read_exact(&mut name)?;
+    String::from_utf8(name).map_err(|_| invalid(format!("the {} is not valid utf-8", what)))
+}
+
+fn read_u8(r: &mut impl Read) -> io::Result<u8> {
+    let mut buf = [0];
+    r.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn write_f32s(w: &mut impl Write, values: &[f32]) -> io::Result<()> {
+    values.iter().try_for_each(|v| w.write_all(&v.to_le_bytes()))
+}
+
+fn read_vec3(r: &mut impl Read) -> io::Result<Vec3> {
+    Ok(Vec3::new(read_f32(r)?, read_f32(r)?, read_f32(r)?))
+}
+
+pub(crate) fn read_u32(r: &mut impl Read) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+pub(crate) fn read_u64(r: &mut impl Read) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+pub(crate) fn read_f32(r: &mut impl Read) -> io::Result<f32> {
+    Ok(f32::from_bits(read_u32(r)?))
+}
+
+//count, sum, mean and m2 of a pixel
+pub(crate) fn write_stats(w: &mut impl Write, pixel: &PixelStats) -> io::Result<()> {
+    w.write_all(&pixel.count.to_le_bytes())?;
+    for v in [pixel.sum.x, pixel.sum.y, pixel.sum.z, pixel.mean, pixel.m2] {
+        w.write_all(&v.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+pub(crate) fn read_stats(r: &mut impl Read) -> io::Result<PixelStats> {
+    Ok(PixelStats {
+        count: read_u32(r)?,
+        sum: Vec3::new(read_f32(r)?, read_f32(r)?, read_f32(r)?),
+        mean: read_f32(r)?,
+        m2: read_f32(r)?,
+    })
+}
+
+impl Checkpoint {
+    //little endian: magic, width, height, seed, sample count, sampler name, a spectral byte, bounces, min bounces,
+    //a byte telling if threshold and min samples of adaptive sampling follow, the same for the absorption,
+    //scattering, g and depth of the atmosphere, scene hash, scene name,
+    //then count, sum, mean and m2 of every pixel, names are prefixed with their length
+    //the file is written next to path first, a crash while saving keeps the old checkpoint
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let mut file = BufWriter::new(File::create(&tmp)?);
+        file.write_all(MAGIC)?;
+        file.write_all(&(self.frame.width as u32).to_le_bytes())?;
+        file.write_all(&(self.frame.height as u32).to_le_bytes())?;
+        file.write_all(&self.seed.to_le_bytes())?;
+        file.write_all(&self.sample_count.to_le_bytes())?;
+        write_name(&mut file, self.sampler.name())?;
+        file.write_all(&[self.spectral as u8, self.bounce_count, self.min_bounce_count])?;
+        file.write_all(&[self.adaptive.is_some() as u8])?;
+        if let Some(adaptive) = &self.adaptive {
+            file.write_all(&adaptive.threshold.to_le_bytes())?;
+            file.write_all(&adaptive.min_sample_count.to_le_bytes())?;
+        }
+        file.write_all(&[self.atmosphere.is_some() as u8])?;
+        if let Some(Atmosphere { medium: Medium { absorption: a, scattering: s, g }, depth }) = &self.atmosphere {
+            write_f32s(&mut file, &[a.x, a.y, a.z, s.x, s.y, s.z, *g, *depth])?;
+        }
+        file.write_all(&self.scene_hash.to_le_bytes())?;
+        write_name(&mut file, &self.scene)?;
+        for pixel in &self.frame.stats {
+            write_stats(&mut file, pixel)?;
+        }
+        file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        fs::rename(&tmp, path)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut file = BufReader::new(File::open(path)?);
+        let mut magic = [0; 8];
+        file.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid(format!("{} is not a checkpoint", path.display())));
+        }
+        let width = read_u32(&mut file)? as usize;
+        let height = read_u32(&mut file)? as usize;
+        if width > MAX_SIDE || height > MAX_SIDE {
+            return Err(invalid(format!("the size {}x{} is too large", width, height)));
+        }
+        let seed = read_u64(&mut file)?;
+        let sample_count = read_u32(&mut file)?;
+        let sampler: SamplerKind = read_name(&mut file, 64, "sampler name")?.parse().map_err(invalid)?;
+        let spectral = read_u8(&mut file)? == 1;
+        let bounce_count = read_u8(&mut file)?;
+        let min_bounce_count = read_u8(&mut file)?;
+        let adaptive = match read_u8(&mut file)? {
+            0 => None,
+            _ => Some(Adaptive { threshold: read_f32(&mut file)?, min_sample_count: read_u32(&mut file)? }),
+        };
+        let atmosphere = match read_u8(&mut file)? {
+            0 => None,
+            _ => {
+                let medium = Medium { absorption: read_vec3(&mut file)?, scattering: read_vec3(&mut file)?, g: read_f32(&mut file)? };
+                Some(Atmosphere { medium, depth: read_f32(&mut file)? })
+            }
+        };
+        let scene_hash = read_u64(&mut file)?;
+        let scene = read_name(&mut file, 4096, "scene name")?;
+        //the pixels have to fill the rest of the file exactly
+        let pixels = (width as u64).checked_mul(height as u64).and_then(|n| n.checked_mul(PIXEL_SIZE));
+        let left = file.get_ref().metadata()?.len().saturating_sub(file.stream_position()?);
+        if pixels != Some(left) {
+            return Err(invalid(format!("{} is truncated or corrupt", path.display())));
+        }
+        let mut frame = Frame::new(width, height);
+        for pixel in frame.stats.iter_mut() {
+            *pixel = read_stats(&mut file)?;
+        }
+        Ok(Self { scene, scene_hash, sample_count, seed, sampler, spectral, bounce_count, min_bounce_count, adaptive, atmosphere, frame })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checkpoint() -> Checkpoint {
+        let mut frame = Frame::new(3, 2);
+        for (i, pixel) in frame.stats.iter_mut().enumerate() {
+            for s in 0..=i {
+                pixel.add(&Vec3::new(i as f32, s as f32 * 0.5, 1.));
+            }
+        }
+        let medium = Medium { absorption: Vec3::new(0.1, 0.2, 0.3), scattering: Vec3::new1(0.5), g: -0.3 };
+        Checkpoint {
+            scene: "scenes/fog.scene".to_string(),
+            scene_hash: source_hash(b"samples 64\n"),
+            sample_count: 64,
+            seed: 9,
+            sampler: SamplerKind::Halton,
+            spectral: true,
+            bounce_count: 7,
+            min_bounce_count: 2,
+            adaptive: Some(Adaptive { threshold: 0.02, min_sample_count: 8 }),
+            atmosphere: Some(Atmosphere { medium, depth: 20. }),
+            frame,
+        }
+    }
+
+    #[test]
+    fn save_then_load_gives_the_same_checkpoint() {
+        let path = std::env::temp_dir().join(format!("raytracing-checkpoint-{}", std::process::id()));
+        let saved = checkpoint();
+        saved.save(&path).unwrap();
+        let loaded = Checkpoint::load(&path);
+        let truncated = fs::read(&path).map(|bytes| fs::write(&path, &bytes[..bytes.len() - 1]));
+        let broken = Checkpoint::load(&path);
+        fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+        assert_eq!((&loaded.scene, loaded.scene_hash), (&saved.scene, saved.scene_hash));
+        assert_eq!((loaded.bounce_count, loaded.min_bounce_count, loaded.adaptive), (7, 2, saved.adaptive));
+        assert_eq!(loaded.atmosphere, saved.atmosphere);
+        assert_eq!((loaded.sample_count, loaded.seed, loaded.sampler, loaded.spectral), (64, 9, SamplerKind::Halton, true));
+        assert_eq!((loaded.frame.width, loaded.frame.height), (3, 2));
+        for (a, b) in loaded.frame.stats.iter().zip(&saved.frame.stats) {
+            assert_eq!((a.count, &a.sum, a.mean, a.m2), (b.count, &b.sum, b.mean, b.m2));
+        }
+        assert!(truncated.is_ok());
+        assert!(broken.is_err(), "a truncated checkpoint was loaded");
+    }
+}
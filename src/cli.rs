@@ -0,0 +1,345 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use image::ImageFormat;
+use raytracing::{Uniforms, Adaptive, Vec3, scene_file, checkpoint::source_hash, output::HdrFormat, sampler::SamplerKind, tiles::TileOrder, medium::{Atmosphere, Medium}};
+use crate::scenes;
+
+pub const USAGE: &str = "\
+usage: raytracing [options]
+
+options:
+  -s, --scene <name>          scene to render: lalaland, cylinder_test, scene3, fast (default: lalaland)
+  -f, --file <path>           render a scene description file instead of a built-in scene
+  -n, --samples <count>       samples per pixel, the most a pixel can get with --adaptive (default: 50, or the samples of the scene file)
+      --adaptive <error>      stop sampling a pixel once its relative error is below this, e.g. 0.01
+      --min-samples <count>   samples every pixel gets before --adaptive may stop it (default: 16)
+      --sample-map <path>     save how many samples every pixel took, .exr, .hdr and .pfm keep the counts
+  -b, --bounces <count>       maximum ray bounces (default: the scene's own value)
+      --min-bounces <count>   bounces before paths may be ended at random (default: the scene's own value)
+      --sampler <name>        independent, stratified, halton or sobol (default: the scene's own value)
+      --seed <number>         renders with the same seed are identical (default: the scene's own value)
+      --spectral              trace wavelengths instead of rgb, glass with a dispersive ior splits light into colors
+      --atmosphere <density>  fill the scene with white haze that scatters this much light per unit of distance
+  -r, --resolution <WxH>      output image size, e.g. 800x600 (default: 500x500)
+  -o, --output <path>         where to save the rendered image (default: sample.png),
+                              .exr, .hdr and .pfm keep the full dynamic range
+      --tile-size <px>        edge length of the squares the threads render (default: the scene's own value)
+      --tile-order <name>     scanline, spiral or hilbert (default: the scene's own value)
+      --progressive <count>   render in passes of this many samples per pixel and save the image after each pass
+      --snapshot-every <secs> save the image and the checkpoint at most this often
+                              (default: after every pass, or every 5 minutes without --progressive)
+      --checkpoint <path>     keep a checkpoint of the render there, to continue it with --resume
+      --resume <path>         continue the render of a checkpoint with the same scene file and settings,
+                              -n can raise its sample count
+      --denoise-output <path> where to save the denoised image (default: sample2.png)
+      --denoise-radius <px>   kernel radius of the denoiser (default: 20)
+      --denoise               save a denoised copy of the render (default)
+      --no-denoise            skip denoising
+      --workers <list>        render on worker processes instead, a comma separated list of
+                              'local' (a child process) and host:port (a worker started with --listen),
+                              workers read textures and meshes from the same paths in their own working directory
+      --worker                act as a worker on stdin and stdout, started by --workers local
+      --listen <addr>         act as a worker for coordinators that connect to addr, e.g. 0.0.0.0:7000
+  -h, --help                  print this message";
+
+//how often a render without --progressive writes its checkpoint
+pub const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(300);
+
+//samples per pixel of the built-in scenes when -n is not given
+pub const DEFAULT_SAMPLES: u32 = 50;
+
+pub const SCENES: [&str; 4] = ["lalaland", "cylinder_test", "scene3", "fast"];
+
+#[derive(Debug)]
+pub struct Options {
+    pub scene: String,
+    pub scene_file: Option<PathBuf>,
+    pub sample_count: Option<u32>,
+    pub adaptive_threshold: Option<f32>,
+    pub min_sample_count: Option<u32>,
+    pub sample_map: Option<PathBuf>,
+    pub bounce_count: Option<u8>,
+    pub min_bounce_count: Option<u8>,
+    pub sampler: Option<SamplerKind>,
+    pub seed: Option<u64>,
+    pub spectral: bool,
+    //scattering of a gray atmosphere over the whole scene
+    pub atmosphere: Option<f32>,
+    pub tile_size: Option<usize>,
+    pub tile_order: Option<TileOrder>,
+    pub resolution: Option<(usize, usize)>,
+    pub output: PathBuf,
+    //samples per pass
+    pub progressive: Option<u32>,
+    pub snapshot_interval: Option<Duration>,
+    pub checkpoint: Option<PathBuf>,
+    pub resume: Option<PathBuf>,
+    pub workers: Vec<WorkerAddr>,
+    pub denoise: bool,
+    pub denoise_radius: u32,
+    pub denoise_output: PathBuf,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            scene: "lalaland".to_string(),
+            scene_file: None,
+            sample_count: None,
+            adaptive_threshold: None,
+            min_sample_count: None,
+            sample_map: None,
+            bounce_count: None,
+            min_bounce_count: None,
+            sampler: None,
+            seed: None,
+            spectral: false,
+            atmosphere: None,
+            tile_size: None,
+            tile_order: None,
+            resolution: None,
+            output: PathBuf::from("sample.png"),
+            progressive: None,
+            snapshot_interval: None,
+            checkpoint: None,
+            resume: None,
+            workers: vec![],
+            denoise: true,
+            denoise_radius: 20,
+            denoise_output: PathBuf::from("sample2.png"),
+        }
+    }
+}
+
+pub enum Command {
+    Render(Box<Options>),
+    Help,
+    //serve one coordinator on stdin and stdout
+    Worker,
+    //serve coordinators connecting to the address
+    Listen(String),
+}
+
+//where a coordinator finds a worker
+#[derive(Debug, Clone, PartialEq)]
+pub enum WorkerAddr {
+    Local,
+    Tcp(String),
+}
+
+fn value<'a>(flag: &str, args: &mut impl Iterator<Item = &'a String>) -> Result<&'a String, String> {
+    args.next().ok_or(format!("missing value for {}", flag))
+}
+
+fn number<T: std::str::FromStr>(flag: &str, text: &str) -> Result<T, String> {
+    text.parse().map_err(|_| format!("invalid value '{}' for {}: expected a positive whole number", text, flag))
+}
+
+fn resolution(text: &str) -> Result<(usize, usize), String> {
+    let err = || format!("invalid resolution '{}': expected WIDTHxHEIGHT, e.g. 800x600", text);
+    let (w, h) = text.split_once(['x', 'X']).ok_or_else(err)?;
+    let width: usize = w.parse().map_err(|_| err())?;
+    let height: usize = h.parse().map_err(|_| err())?;
+    if width == 0 || height == 0 {
+        return Err(format!("invalid resolution '{}': width and height must be greater than zero", text));
+    }
+    Ok((width, height))
+}
+
+//the 8 bit formats that can be written from rgb
+const IMAGE_FORMATS: [ImageFormat; 5] = [ImageFormat::Png, ImageFormat::Jpeg, ImageFormat::Bmp, ImageFormat::Tiff, ImageFormat::Tga];
+
+//an image path with an extension that can be saved, hdr allows .exr, .hdr and .pfm besides the 8 bit formats
+fn output(flag: &str, text: &str, hdr: bool) -> Result<PathBuf, String> {
+    let path = PathBuf::from(text);
+    let supported = match HdrFormat::from_path(&path) {
+        Some(_) => hdr,
+        None => ImageFormat::from_path(&path).is_ok_and(|format| format.writing_enabled() && IMAGE_FORMATS.contains(&format)),
+    };
+    if !supported {
+        let formats = if hdr { ".png, .jpg, .bmp, .tiff, .tga, .exr, .hdr or .pfm" } else { ".png, .jpg, .bmp, .tiff or .tga" };
+        return Err(format!("can't save '{}' for {}: expected {}", text, flag, formats));
+    }
+    Ok(path)
+}
+
+//args should not contain the program name
+pub fn parse(args: &[String]) -> Result<Command, String> {
+    let mut opts = Options::default();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--worker" => return Ok(Command::Worker),
+            "--listen" => return Ok(Command::Listen(value(arg, &mut iter)?.clone())),
+            "--workers" => {
+                let list = value(arg, &mut iter)?;
+                opts.workers = list.split(',').map(|w| match w.trim() {
+                    "local" => Ok(WorkerAddr::Local),
+                    addr if addr.contains(':') => Ok(WorkerAddr::Tcp(addr.to_string())),
+                    other => Err(format!("invalid worker '{}': expected local or host:port", other)),
+                }).collect::<Result<Vec<WorkerAddr>, String>>()?;
+            }
+            "-s" | "--scene" => {
+                let name = value(arg, &mut iter)?;
+                if !SCENES.contains(&name.as_str()) {
+                    return Err(format!("unknown scene '{}', available scenes: {}", name, SCENES.join(", ")));
+                }
+                opts.scene = name.clone();
+            }
+            "-f" | "--file" => opts.scene_file = Some(PathBuf::from(value(arg, &mut iter)?)),
+            "-n" | "--samples" => {
+                let count: u32 = number(arg, value(arg, &mut iter)?)?;
+                if count == 0 {
+                    return Err(format!("{} must be at least 1", arg));
+                }
+                opts.sample_count = Some(count);
+            }
+            "--adaptive" => {
+                let text = value(arg, &mut iter)?;
+                let threshold: f32 = text.parse().map_err(|_| format!("invalid value '{}' for {}: expected a number", text, arg))?;
+                if threshold.is_nan() || threshold <= 0. {
+                    return Err(format!("{} must be greater than zero", arg));
+                }
+                opts.adaptive_threshold = Some(threshold);
+            }
+            "--min-samples" => opts.min_sample_count = Some(number(arg, value(arg, &mut iter)?)?),
+            "--sample-map" => opts.sample_map = Some(output(arg, value(arg, &mut iter)?, true)?),
+            "-b" | "--bounces" => opts.bounce_count = Some(number(arg, value(arg, &mut iter)?)?),
+            "--min-bounces" => opts.min_bounce_count = Some(number(arg, value(arg, &mut iter)?)?),
+            "--sampler" => opts.sampler = Some(value(arg, &mut iter)?.parse()?),
+            "--tile-size" => {
+                let size: usize = number(arg, value(arg, &mut iter)?)?;
+                if size == 0 {
+                    return Err(format!("{} must be at least 1", arg));
+                }
+                opts.tile_size = Some(size);
+            }
+            "--tile-order" => opts.tile_order = Some(value(arg, &mut iter)?.parse()?),
+            "--seed" => opts.seed = Some(number(arg, value(arg, &mut iter)?)?),
+            "--spectral" => opts.spectral = true,
+            "--atmosphere" => {
+                let text = value(arg, &mut iter)?;
+                let density: f32 = text.parse().map_err(|_| format!("invalid value '{}' for {}: expected a number", text, arg))?;
+                if !density.is_finite() || density < 0. {
+                    return Err(format!("{} must not be negative", arg));
+                }
+                opts.atmosphere = Some(density);
+            }
+            "-r" | "--resolution" => opts.resolution = Some(resolution(value(arg, &mut iter)?)?),
+            "-o" | "--output" => opts.output = output(arg, value(arg, &mut iter)?, true)?,
+            "--progressive" => {
+                let pass_size: u32 = number(arg, value(arg, &mut iter)?)?;
+                if pass_size == 0 {
+                    return Err(format!("{} must be at least 1", arg));
+                }
+                opts.progressive = Some(pass_size);
+            }
+            "--snapshot-every" => {
+                let text = value(arg, &mut iter)?;
+                let secs: f32 = text.parse().map_err(|_| format!("invalid value '{}' for {}: expected seconds", text, arg))?;
+                if !secs.is_finite() || secs < 0. {
+                    return Err(format!("{} must not be negative", arg));
+                }
+                opts.snapshot_interval = Some(Duration::from_secs_f32(secs));
+            }
+            "--checkpoint" => opts.checkpoint = Some(PathBuf::from(value(arg, &mut iter)?)),
+            "--resume" => opts.resume = Some(PathBuf::from(value(arg, &mut iter)?)),
+            "--denoise-output" => opts.denoise_output = output(arg, value(arg, &mut iter)?, false)?,
+            "--denoise-radius" => opts.denoise_radius = number(arg, value(arg, &mut iter)?)?,
+            "--denoise" => opts.denoise = true,
+            "--no-denoise" => opts.denoise = false,
+            other => return Err(format!("unknown argument '{}'", other)),
+        }
+    }
+    if !opts.workers.is_empty() && (opts.progressive.is_some() || opts.checkpoint.is_some() || opts.resume.is_some()) {
+        return Err("--workers can't be combined with --progressive, --checkpoint or --resume".to_string());
+    }
+    if opts.snapshot_interval.is_some() && opts.progressive.is_none() && opts.checkpoint.is_none() {
+        return Err("--snapshot-every needs --progressive or --checkpoint".to_string());
+    }
+    Ok(Command::Render(Box::new(opts)))
+}
+
+//the scene a render is of, a checkpoint can only be resumed with the same one
+pub fn scene_name(opts: &Options) -> String {
+    match &opts.scene_file {
+        Some(path) => std::fs::canonicalize(path).unwrap_or(path.clone()).display().to_string(),
+        None => opts.scene.clone(),
+    }
+}
+
+//the content of the scene file, a checkpoint is only resumed while it stays the same, 0 for a built-in scene
+pub fn scene_hash(opts: &Options) -> Result<u64, String> {
+    match &opts.scene_file {
+        Some(path) => std::fs::read(path).map(|src| source_hash(&src)).map_err(|e| format!("could not read {}: {}", path.display(), e)),
+        None => Ok(0),
+    }
+}
+
+pub fn load_scene(opts: &Options) -> Result<Uniforms, String> {
+    load_scene_from(opts, None)
+}
+
+//scene_source replaces the content of the --file scene, workers get it from the coordinator
+pub fn load_scene_from(opts: &Options, scene_source: Option<&str>) -> Result<Uniforms, String> {
+    let mut uni = match (&opts.scene_file, scene_source) {
+        (Some(path), Some(src)) => {
+            let base_dir = path.parent().unwrap_or(Path::new("."));
+            scene_file::parse(src, base_dir).map_err(|e| format!("{}: {}", path.display(), e))?
+        }
+        (Some(path), None) => scene_file::load(path).map_err(|e| format!("{}: {}", path.display(), e))?,
+        (None, _) => match opts.scene.as_str() {
+            "cylinder_test" => scenes::cylinder_test(),
+            "scene3" => scenes::scene3(),
+            "fast" => scenes::fast(),
+            _ => scenes::lalaland()?,
+        },
+    };
+    if opts.scene_file.is_none() {
+        uni.sample_count = DEFAULT_SAMPLES;
+    }
+    if let Some(count) = opts.sample_count {
+        uni.sample_count = count;
+    }
+    if let Some(threshold) = opts.adaptive_threshold {
+        uni.adaptive = Some(Adaptive { threshold, ..uni.adaptive.unwrap_or_default() });
+    }
+    //only changes adaptive sampling that --adaptive or the scene turned on
+    if let Some(count) = opts.min_sample_count {
+        let Some(adaptive) = uni.adaptive.as_mut() else {
+            return Err("--min-samples needs --adaptive or a scene with adaptive sampling".to_string());
+        };
+        adaptive.min_sample_count = count;
+    }
+    if let Some(count) = opts.bounce_count {
+        uni.bounce_count = count;
+    }
+    if let Some(count) = opts.min_bounce_count {
+        uni.min_bounce_count = count;
+    }
+    if let Some(sampler) = opts.sampler {
+        uni.sampler = sampler;
+    }
+    if let Some(seed) = opts.seed {
+        uni.seed = seed;
+    }
+    if opts.spectral {
+        uni.spectral = true;
+    }
+    if let Some(density) = opts.atmosphere {
+        let medium = Medium { absorption: Vec3::default(), scattering: Vec3::new1(density), g: 0. };
+        let depth = uni.atmosphere.as_ref().map_or(Atmosphere::DEFAULT_DEPTH, |a| a.depth);
+        uni.atmosphere = Some(Atmosphere { medium, depth });
+    }
+    if let Some(size) = opts.tile_size {
+        uni.tile_size = size;
+    }
+    if let Some(order) = opts.tile_order {
+        uni.tile_order = order;
+    }
+    if let Some((width, height)) = opts.resolution {
+        uni.set_resolution(width, height);
+    }
+    Ok(uni)
+}
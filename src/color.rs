@@ -0,0 +1,81 @@
+use std::sync::OnceLock;
+use crate::Vec3;
+
+//how the 8 bit values of a Picture relate to light, the renderer itself always works in linear
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ColorSpace {
+    Linear,
+    //https://en.wikipedia.org/wiki/SRGB#Transfer_function_(%22gamma%22)
+    #[default]
+    Srgb,
+}
+
+impl ColorSpace {
+    //8 bit value to linear 0-1
+    pub fn decode(&self, value: u8) -> f32 {
+        match self {
+            Self::Linear => value as f32 / 255.,
+            Self::Srgb => srgb_table()[value as usize],
+        }
+    }
+
+    //linear value to 8 bit, values outside of 0-1 are clamped
+    pub fn encode(&self, value: f32) -> u8 {
+        let v = value.clamp(0., 1.);
+        let encoded = match self {
+            Self::Linear => v,
+            Self::Srgb => linear_to_srgb(v),
+        };
+        (encoded * 255. + 0.5) as u8
+    }
+
+    pub fn decode_vec(&self, r: u8, g: u8, b: u8) -> Vec3 {
+        Vec3::new(self.decode(r), self.decode(g), self.decode(b))
+    }
+}
+
+pub fn srgb_to_linear(v: f32) -> f32 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(v: f32) -> f32 {
+    if v <= 0.0031308 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1. / 2.4) - 0.055
+    }
+}
+
+//texture lookups happen on every hit, the 256 possible values are decoded once
+fn srgb_table() -> &'static [f32; 256] {
+    static TABLE: OnceLock<[f32; 256]> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut table = [0.; 256];
+        for (i, v) in table.iter_mut().enumerate() {
+            *v = srgb_to_linear(i as f32 / 255.);
+        }
+        table
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_8_bit_value_survives_decoding_and_encoding() {
+        for space in [ColorSpace::Linear, ColorSpace::Srgb] {
+            for value in 0..=255 {
+                assert_eq!(space.encode(space.decode(value)), value, "{:?} changed {}", space, value);
+            }
+        }
+        //middle gray is much darker in linear light, values outside of 0-1 are clamped
+        assert!((ColorSpace::Srgb.decode(128) - 0.2158).abs() < 1e-3);
+        assert_eq!((ColorSpace::Srgb.encode(-1.), ColorSpace::Srgb.encode(4.)), (0, 255));
+        assert!((srgb_to_linear(linear_to_srgb(0.001)) - 0.001).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,323 @@
+//rendering on worker processes over their stdio or tcp, the coordinator sends the scene once and then one tile at a time,
+//workers load the images and meshes of the scene from the same paths relative to their own working directory
+
+use std::collections::VecDeque;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::sync::mpsc;
+use std::thread;
+use indicatif::ProgressBar;
+use crate::{
+    Uniforms,
+    checkpoint::{read_stats, read_u32, write_stats},
+    render::{Frame, PixelStats, render_tile},
+    tiles::Tile,
+};
+
+pub enum Message {
+    //the command line of the coordinator, with the text of the scene file if it uses one,
+    //files the scene refers to must exist at the same path on the worker
+    Scene { args: Vec<String>, scene_source: Option<String> },
+    Job { id: u32, tile: Tile },
+    //the stats of the tile row by row
+    Result { id: u32, stats: Vec<PixelStats> },
+    Error(String),
+    Done,
+}
+
+const SCENE: u8 = 1;
+const JOB: u8 = 2;
+const RESULT: u8 = 3;
+const ERROR: u8 = 4;
+const DONE: u8 = 5;
+
+//the longest string and the most arguments a message can have, a scene file is far shorter
+const MAX_TEXT: usize = 1 << 26;
+const MAX_ARGS: u32 = 1 << 16;
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn write_u32(w: &mut impl Write, v: u32) -> io::Result<()> {
+    w.write_all(&v.to_le_bytes())
+}
+
+fn write_str(w: &mut impl Write, s: &str) -> io::Result<()> {
+    write_u32(w, s.len() as u32)?;
+    w.write_all(s.as_bytes())
+}
+
+fn read_str(r: &mut impl Read) -> io::Result<String> {
+    let len = read_u32(r)? as usize;
+    if len > MAX_TEXT {
+        return Err(invalid(format!("a string of {} bytes is too long", len)));
+    }
+    let mut buf = vec![0; len];
+    r.read_exact(&mut buf)?;
+    String::from_utf8(buf).map_err(|e| invalid(e.to_string()))
+}
+
+//a tag byte followed by the fields, numbers are little endian and strings are prefixed with their length
+pub fn write_message(w: &mut impl Write, msg: &Message) -> io::Result<()> {
+    match msg {
+        Message::Scene { args, scene_source } => {
+            w.write_all(&[SCENE])?;
+            write_u32(w, args.len() as u32)?;
+            for arg in args {
+                write_str(w, arg)?;
+            }
+            match scene_source {
+                Some(src) => {
+                    w.write_all(&[1])?;
+                    write_str(w, src)?;
+                }
+                None => w.write_all(&[0])?,
+            }
+        }
+        Message::Job { id, tile } => {
+            w.write_all(&[JOB])?;
+            for v in [*id, tile.x0 as u32, tile.y0 as u32, tile.x1 as u32, tile.y1 as u32] {
+                write_u32(w, v)?;
+            }
+        }
+        Message::Result { id, stats } => {
+            w.write_all(&[RESULT])?;
+            write_u32(w, *id)?;
+            write_u32(w, stats.len() as u32)?;
+            for pixel in stats {
+                write_stats(w, pixel)?;
+            }
+        }
+        Message::Error(msg) => {
+            w.write_all(&[ERROR])?;
+            write_str(w, msg)?;
+        }
+        Message::Done => w.write_all(&[DONE])?,
+    }
+    w.flush()
+}
+
+//max_pixels is the size of the tile a result is expected for, bigger results are refused before they are read
+pub fn read_message(r: &mut impl Read, max_pixels: usize) -> io::Result<Message> {
+    let mut tag = [0];
+    r.read_exact(&mut tag)?;
+    match tag[0] {
+        SCENE => {
+            let count = read_u32(r)?;
+            if count > MAX_ARGS {
+                return Err(invalid(format!("{} arguments are too many", count)));
+            }
+            let args = (0..count).map(|_| read_str(r)).collect::<io::Result<Vec<String>>>()?;
+            let mut has_source = [0];
+            r.read_exact(&mut has_source)?;
+            let scene_source = if has_source[0] == 1 { Some(read_str(r)?) } else { None };
+            Ok(Message::Scene { args, scene_source })
+        }
+        JOB => {
+            let id = read_u32(r)?;
+            let mut v = [0; 4];
+            for c in v.iter_mut() {
+                *c = read_u32(r)? as usize;
+            }
+            Ok(Message::Job { id, tile: Tile { x0: v[0], y0: v[1], x1: v[2], y1: v[3] } })
+        }
+        RESULT => {
+            let id = read_u32(r)?;
+            let count = read_u32(r)?;
+            if count as usize > max_pixels {
+                return Err(invalid(format!("a result of {} pixels is larger than its tile", count)));
+            }
+            let stats = (0..count).map(|_| read_stats(r)).collect::<io::Result<Vec<PixelStats>>>()?;
+            Ok(Message::Result { id, stats })
+        }
+        ERROR => Ok(Message::Error(read_str(r)?)),
+        DONE => Ok(Message::Done),
+        other => Err(invalid(format!("unknown message {}", other))),
+    }
+}
+
+//the worker side of one connection: builds the scene with setup and renders jobs until the coordinator is done
+pub fn serve<F, S>(reader: impl Read, writer: impl Write, setup: S, func: F) -> io::Result<()>
+where
+    F: Fn(usize, usize, &Uniforms, &mut PixelStats, u32) + Sync + Send,
+    S: FnOnce(&[String], Option<&str>) -> Result<Uniforms, String>,
+{
+    let mut reader = BufReader::new(reader);
+    let mut writer = BufWriter::new(writer);
+    //a worker never gets results
+    let mut input = match read_message(&mut reader, 0)? {
+        Message::Scene { args, scene_source } => match setup(&args, scene_source.as_deref()) {
+            Ok(input) => input,
+            Err(msg) => return write_message(&mut writer, &Message::Error(msg)),
+        },
+        _ => return write_message(&mut writer, &Message::Error("expected the scene first".to_string())),
+    };
+    input.prepare();
+    loop {
+        match read_message(&mut reader, 0) {
+            Ok(Message::Job { id, tile }) => {
+                if tile.x1 > input.cam.width || tile.y1 > input.cam.height || tile.x0 > tile.x1 || tile.y0 > tile.y1 {
+                    return write_message(&mut writer, &Message::Error(format!("tile {:?} is outside of the image", tile)));
+                }
+                let stats = render_tile(&func, &input, &tile, input.sample_count);
+                write_message(&mut writer, &Message::Result { id, stats })?;
+            }
+            Ok(Message::Done) => return Ok(()),
+            //the coordinator went away
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(err) => return Err(err),
+            Ok(_) => return write_message(&mut writer, &Message::Error("expected a job".to_string())),
+        }
+    }
+}
+
+//one connection to a worker, name is only used in messages
+pub struct Worker {
+    pub name: String,
+    pub reader: Box<dyn Read + Send>,
+    pub writer: Box<dyn Write + Send>,
+}
+
+enum Event {
+    Finished { worker: usize, id: u32, stats: Vec<PixelStats> },
+    //the job goes back into the queue, the worker gets no more
+    Lost { worker: usize, id: u32, err: String },
+    //the scene doesn't load, every worker would fail the same way
+    Failed(String),
+}
+
+//runs on its own thread and passes the jobs it gets to the worker
+fn drive(index: usize, worker: Worker, scene: &Message, jobs: mpsc::Receiver<(u32, Tile)>, events: mpsc::Sender<Event>) {
+    let mut reader = BufReader::new(worker.reader);
+    let mut writer = BufWriter::new(worker.writer);
+    let mut scene_sent = false;
+    for (id, tile) in jobs {
+        //the scene goes with the first job, so a failure always has a job to give back
+        let send_scene = if scene_sent { Ok(()) } else { write_message(&mut writer, scene) };
+        scene_sent = true;
+        let reply = send_scene
+            .and_then(|_| write_message(&mut writer, &Message::Job { id, tile }))
+            .and_then(|_| read_message(&mut reader, tile.width() * tile.height()));
+        //a reply for another job is as broken as no reply
+        let event = match reply {
+            Ok(Message::Result { id: got, stats }) if got == id && stats.len() == tile.width() * tile.height() => {
+                Event::Finished { worker: index, id, stats }
+            }
+            Ok(Message::Error(msg)) => Event::Failed(format!("{}: {}", worker.name, msg)),
+            Ok(_) => Event::Lost { worker: index, id, err: "unexpected reply".to_string() },
+            Err(err) => Event::Lost { worker: index, id, err: err.to_string() },
+        };
+        let stop = !matches!(event, Event::Finished { .. });
+        if events.send(event).is_err() || stop {
+            return;
+        }
+    }
+    if scene_sent {
+        let _ = write_message(&mut writer, &Message::Done);
+    }
+}
+
+//renders the tiles on the workers, a worker that breaks down only loses its current tile to the others
+pub fn coordinate(workers: Vec<Worker>, scene: Message, width: usize, height: usize, tiles: Vec<Tile>) -> Result<Frame, String> {
+    assert!(!workers.is_empty(), "coordinate needs at least one worker");
+    let mut frame = Frame::new(width, height);
+    let mut queue: VecDeque<(u32, Tile)> = tiles.iter().enumerate().map(|(i, tile)| (i as u32, *tile)).collect();
+    let names: Vec<String> = workers.iter().map(|w| w.name.clone()).collect();
+    let (event_sender, events) = mpsc::channel();
+    let pb = ProgressBar::new(tiles.len() as u64);
+    let result = thread::scope(|scope| {
+        let mut job_senders: Vec<Option<mpsc::Sender<(u32, Tile)>>> = vec![];
+        for (i, worker) in workers.into_iter().enumerate() {
+            let (job_sender, jobs) = mpsc::channel();
+            let (scene, events) = (&scene, event_sender.clone());
+            scope.spawn(move || drive(i, worker, scene, jobs, events));
+            job_senders.push(Some(job_sender));
+        }
+        drop(event_sender);
+        let mut idle: Vec<usize> = (0..job_senders.len()).rev().collect();
+        let mut remaining = tiles.len();
+        while remaining > 0 {
+            //every idle worker gets the next tile
+            while let (Some(&worker), Some(job)) = (idle.last(), queue.front()) {
+                idle.pop();
+                if let Some(sender) = &job_senders[worker] {
+                    if sender.send(*job).is_ok() {
+                        queue.pop_front();
+                    }
+                }
+            }
+            match events.recv() {
+                Ok(Event::Finished { worker, id, stats }) => {
+                    frame.set_tile(&tiles[id as usize], &stats);
+                    remaining -= 1;
+                    pb.inc(1);
+                    idle.push(worker);
+                }
+                Ok(Event::Lost { worker, id, err }) => {
+                    pb.suspend(|| eprintln!("worker {} stopped: {}", names[worker], err));
+                    job_senders[worker] = None;
+                    queue.push_front((id, tiles[id as usize]));
+                    if job_senders.iter().all(|s| s.is_none()) {
+                        return Err("every worker stopped".to_string());
+                    }
+                }
+                Ok(Event::Failed(msg)) => return Err(msg),
+                Err(_) => return Err("every worker stopped".to_string()),
+            }
+        }
+        //closing the job channels lets the workers finish
+        job_senders.clear();
+        Ok(())
+    });
+    pb.finish_and_clear();
+    result.map(|_| frame)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Vec3;
+    use super::*;
+
+    fn round_trip(msg: &Message) -> Message {
+        let mut bytes = vec![];
+        write_message(&mut bytes, msg).unwrap();
+        let mut reader = bytes.as_slice();
+        let read = read_message(&mut reader, 2).unwrap();
+        assert!(reader.is_empty(), "bytes were left after the message");
+        read
+    }
+
+    #[test]
+    fn messages_survive_encoding() {
+        let scene = Message::Scene { args: vec!["-f".to_string(), "fog.scene".to_string()], scene_source: Some("samples 4\n".to_string()) };
+        let Message::Scene { args, scene_source } = round_trip(&scene) else { panic!("not a scene") };
+        assert_eq!(args, ["-f", "fog.scene"]);
+        assert_eq!(scene_source.as_deref(), Some("samples 4\n"));
+        let Message::Scene { scene_source: None, .. } = round_trip(&Message::Scene { args: vec![], scene_source: None }) else {
+            panic!("the scene source appeared")
+        };
+
+        let tile = Tile { x0: 1, y0: 2, x1: 30, y1: 40 };
+        let Message::Job { id: 7, tile: read } = round_trip(&Message::Job { id: 7, tile }) else { panic!("not a job") };
+        assert_eq!(read, tile);
+
+        let mut pixel = PixelStats::default();
+        pixel.add(&Vec3::new(0.5, 1., 2.));
+        pixel.add(&Vec3::new(1., 0., 0.25));
+        let Message::Result { id: 3, stats } = round_trip(&Message::Result { id: 3, stats: vec![pixel.clone(); 2] }) else {
+            panic!("not a result")
+        };
+        assert_eq!(stats.len(), 2);
+        assert_eq!((stats[1].count, &stats[1].sum, stats[1].mean, stats[1].m2), (pixel.count, &pixel.sum, pixel.mean, pixel.m2));
+
+        let Message::Error(msg) = round_trip(&Message::Error("no scene".to_string())) else { panic!("not an error") };
+        assert_eq!(msg, "no scene");
+        assert!(matches!(round_trip(&Message::Done), Message::Done));
+
+        let mut bytes = vec![];
+        write_message(&mut bytes, &Message::Result { id: 3, stats: vec![pixel; 3] }).unwrap();
+        assert!(read_message(&mut bytes.as_slice(), 2).is_err(), "a result larger than its tile was read");
+        let huge = [&[SCENE][..], &1u32.to_le_bytes(), &u32::MAX.to_le_bytes()].concat();
+        assert!(read_message(&mut huge.as_slice(), 0).is_err(), "a string longer than the message was read");
+    }
+}
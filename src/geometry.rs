@@ -1,17 +1,31 @@
-use crate::{vec3::{Point, Vec3}, WIDTH, HEIGHT, textures::Texture};
-use rand::Rng;
+use crate::{vec3::{Point, Vec3}, DEFAULT_WIDTH, DEFAULT_HEIGHT, textures::Texture, lights::Light, sampler::sample_disk, bsdf::{self, Bsdf, ConductorKind}, spectrum::{Ior, Wavelengths}, medium::Interface};
 pub struct HitInfo {
     pub p: Point,
     pub normal: Vec3,
     pub material: Material,
     pub u: f32,
-    pub v: f32
+    pub v: f32,
+    //set when the hit object is in the light list, its emission was already sampled directly
+    pub light: Option<Light>,
+    //set when the hit is the boundary of a Volume, the ray passes it without scattering
+    pub interface: Option<Interface>
 }
 
 impl HitInfo {
-    pub fn get_color(&self, future: Vec3) -> Vec3{
+    //how the surface at the hit scatters light, at the wavelengths of a spectral path or in rgb
+    pub fn bsdf(&self, wavelengths: Option<&mut Wavelengths>) -> Box<dyn Bsdf> {
+        self.material.refl.bsdf(self, wavelengths)
+    }
+
+    //the texture color at the hit point
+    pub fn albedo(&self) -> Vec3{
+        self.lookup(&self.material.tex)
+    }
+
+    //the color of tex at the hit point
+    pub fn lookup(&self, tex: &Texture) -> Vec3{
         let fin_color: Vec3;
-        match &self.material.tex {
+        match tex {
             Texture::Solid { color } => fin_color = color.clone(),
             Texture::Checker{color1, color2, size} => {
                 let x = (self.p.x / size).round() as i32;
@@ -26,24 +40,115 @@ impl HitInfo {
             }
             Texture::Img{img} => {
                 assert!(self.u >= 0. && self.u <= 1. && self.v >= 0. && self.v <= 1., "texture coord not in 0-1");
-                let x = (self.u * (img.width as f32)) as u32;
-                let y = (self.v * (img.height as f32)) as u32;
+                let x = ((self.u * (img.width as f32)) as u32).min(img.width - 1);
+                let y = ((self.v * (img.height as f32)) as u32).min(img.height - 1);
                 let color = img.get_pixel_normalized((x, y));
                 fin_color = color;
             }
         }
-        &self.material.emmision + fin_color * future * 0.9 
+        fin_color
     }
 }
 
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Clone, Debug)]
 pub enum Reflection {
     Diffuse(),
     //roughness is normalized
     Metal{roughness: f32},
-    Glass{reflective: f32}
+    Glass{reflective: f32},
+    //ggx microfacet metal, eta and k are the complex index of refraction per color channel
+    Conductor{roughness: f32, eta: Vec3, k: Vec3},
+    Principled(Box<Principled>),
+    //glass with an ior that depends on the wavelength, it splits light into colors in the spectral mode
+    Dispersive{ior: Ior}
 }
 
+impl Reflection {
+    //the reflection model with the textures read at the hit, colors are turned into
+    //the values at the wavelengths of a spectral path, dispersive glass ends all but the first of them
+    pub fn bsdf(&self, hit: &HitInfo, wavelengths: Option<&mut Wavelengths>) -> Box<dyn Bsdf> {
+        let albedo = match &wavelengths {
+            Some(w) => w.upsample(&hit.albedo()),
+            None => hit.albedo(),
+        };
+        match self {
+            Self::Diffuse() => Box::new(bsdf::Diffuse { albedo }),
+            Self::Metal { roughness } => Box::new(bsdf::Metal { albedo, roughness: *roughness }),
+            Self::Glass { reflective } => Box::new(bsdf::Glass { tint: albedo, ior: *reflective }),
+            Self::Conductor { roughness, eta, k } => match wavelengths {
+                Some(w) => Box::new(bsdf::Conductor::new(albedo, *roughness, w.interpolate(eta), w.interpolate(k))),
+                None => Box::new(bsdf::Conductor::new(albedo, *roughness, eta.clone(), k.clone())),
+            },
+            //only the first wavelength refracts with its ior
+            Self::Dispersive { ior } => match wavelengths {
+                Some(w) => Box::new(bsdf::Glass { tint: albedo * w.terminate_secondary(), ior: ior.at(w.lambda[0]) }),
+                None => Box::new(bsdf::Glass { tint: albedo, ior: ior.at(Ior::D_LINE) }),
+            },
+            Self::Principled(p) => {
+                //the red channel of the parameter textures
+                let param = |tex: &Texture| hit.lookup(tex).x.clamp(0., 1.);
+                Box::new(bsdf::Principled {
+                    base: albedo,
+                    metallic: param(&p.metallic),
+                    roughness: param(&p.roughness),
+                    specular: param(&p.specular),
+                    clearcoat: param(&p.clearcoat),
+                    sheen: param(&p.sheen),
+                    transmission: param(&p.transmission),
+                    ior: p.ior,
+                })
+            }
+        }
+    }
+
+    //glass like reflections need the outward normal to tell entering and leaving apart
+    pub fn transmits(&self) -> bool {
+        match self {
+            Self::Glass { .. } | Self::Dispersive { .. } => true,
+            Self::Principled(p) => !matches!(&p.transmission, Texture::Solid { color } if color.x <= 0.),
+            _ => false,
+        }
+    }
+
+    pub fn conductor(kind: ConductorKind, roughness: f32) -> Self {
+        let (eta, k) = kind.ior();
+        Self::Conductor { roughness, eta, k }
+    }
+}
+
+
+//the parameters of Reflection::Principled, the base color is the texture of the material,
+//the others are read from the red channel of their textures and are in 0-1
+#[derive(Clone, Debug)]
+pub struct Principled {
+    //0 is a dielectric, 1 a metal
+    pub metallic: Texture,
+    pub roughness: Texture,
+    //reflectance of the dielectric at normal incidence, 0.5 is 4%
+    pub specular: Texture,
+    //a glossy layer on top
+    pub clearcoat: Texture,
+    //soft reflection at grazing angles, like cloth
+    pub sheen: Texture,
+    //how much of the dielectric refracts like glass instead of scattering diffusely
+    pub transmission: Texture,
+    pub ior: f32,
+}
+
+impl Default for Principled {
+    fn default() -> Self {
+        let value = |v: f32| Texture::Solid { color: Vec3::new1(v) };
+        Self {
+            metallic: value(0.),
+            roughness: value(0.5),
+            specular: value(0.5),
+            clearcoat: value(0.),
+            sheen: value(0.),
+            transmission: value(0.),
+            ior: 1.5,
+        }
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct Material {
@@ -67,56 +172,6 @@ impl Default for Material{
         }
     }
 }
-fn reflectance(cos: f32, eta: f32) -> f32 {
-    //Schlick's approximation
-    let r0 = ((1. - eta) / (1. + eta)).powi(2);
-    r0 + (1. - r0) * (1. - cos).powi(5)
-
-}
-
-//the eta is the ratio of the refractive indecies of the second medium and the first medium
-//index1*sin(theta1) = index2*sin(theta2)
-fn snell(incoming: &Vec3, normal: &Vec3, eta: f32) -> Vec3 {
-    let cos_theta = (-1. * incoming).dot(normal).min(1.);
-    let refl = reflectance(cos_theta.abs(), eta) > rand::thread_rng().gen_range(0.0..1.0);
-    //sin^2theta + cos^2theta = 1
-    // total internal reflection
-    if (eta * (1.- cos_theta * cos_theta).abs().sqrt()) > 1. || refl{
-        return (incoming + normal * 2.).normalize()
-    }
-    let r_out_perp: Vec3 = eta * &(incoming + normal * cos_theta);
-    let r_out_parallel: Vec3 = (1.-r_out_perp.length_squared()).abs().sqrt() * -1. * normal;
-    return (r_out_parallel + r_out_perp).normalize();
-}
-
-pub fn scatter(ray: &Ray, hit: &HitInfo) -> Ray {
-    let mut sol: Ray = Ray::new(hit.p.clone(), Vec3::new(0., 0., 0.));
-    match hit.material.refl {
-        Reflection::Diffuse() => {
-            //lambertian reflection
-            sol.dir = (hit.normal + Vec3::random()).normalize();
-
-        } 
-        Reflection::Metal{roughness} => {
-            sol.dir = &ray.dir + &hit.normal * 2.;
-            sol.dir = sol.dir.normalize() + Vec3::random() * roughness;
-            sol.dir = sol.dir.normalize();
-        }
-        Reflection::Glass{reflective} => {
-            if ray.dir.dot(&hit.normal) > 0.{
-                //backface
-                sol.dir = snell(&ray.dir, &(-1. * &hit.normal), reflective);
-            } else {
-                sol.dir = snell(&ray.dir, &hit.normal, 1./reflective);
-
-            }
-            assert!(sol.dir.is_normalized(), "scatter glass");
-        }
-
-    }
-    sol
-}
-
 #[derive(Clone)]
 pub struct Ray {
     pub start: Point,
@@ -130,7 +185,12 @@ impl Ray {
 }
 
 pub struct Camera {
+    pub width: usize,
+    pub height: usize,
     start: Vec3,
+    lookat: Point,
+    vertical_fov: f32,
+    up: Vec3,
     upper_left: Point,
     delta_x: Vec3,
     delta_y: Vec3,
@@ -139,43 +199,61 @@ pub struct Camera {
 
 
 impl Camera {
-    pub fn new(lookfrom: &Point, lookat: &Point, vertical_fov: f32, up: &Vec3, blur: f32) -> Self {
+    pub fn new(lookfrom: &Point, lookat: &Point, vertical_fov: f32, up: &Vec3, blur: f32, width: usize, height: usize) -> Self {
         assert!(up.is_normalized(), "up vector must be normalized at Camera::new");
-        let focal_length = (lookfrom - lookat).length();
-        let theta = vertical_fov.to_radians();
+        let w = (lookfrom - lookat).normalize();
+        assert!(w.dot(up).abs() < 0.999, "up vector must not be parallel to the lookfrom-lookat vector\nat Camera::new");
+        let mut cam = Self {
+            width: 0,
+            height: 0,
+            start: lookfrom.clone(),
+            lookat: lookat.clone(),
+            vertical_fov,
+            up: up.clone(),
+            upper_left: Vec3::default(),
+            delta_x: Vec3::default(),
+            delta_y: Vec3::default(),
+            blur
+        };
+        cam.set_resolution(width, height);
+        cam
+    }
+
+    //where the camera rays start, the center of the lens
+    pub fn origin(&self) -> &Point {
+        &self.start
+    }
+
+    //recalculates the viewport for a new image size, keeping the vertical fov
+    pub fn set_resolution(&mut self, width: usize, height: usize) {
+        assert!(width > 0 && height > 0, "resolution must not be zero at Camera::set_resolution");
+        let focal_length = (&self.start - &self.lookat).length();
+        let theta = self.vertical_fov.to_radians();
         let h = (theta/2.).tan();
 
         let viewport_height = 2. * h * focal_length;
-        let viewport_width = WIDTH as f32 / HEIGHT as f32 * viewport_height;
+        let viewport_width = width as f32 / height as f32 * viewport_height;
 
-        let w = (lookfrom - lookat).normalize();
-        assert!(w.dot(&up).abs() < 0.999, "up vector must not be parallel to the lookfrom-lookat vector\nat Camera::new");
-        let u = up.cross(&w).normalize();
+        let w = (&self.start - &self.lookat).normalize();
+        let u = self.up.cross(&w).normalize();
         let v = w.cross(&u);
 
         let viewport_u = u * viewport_width;
         let viewport_v = -1. * &v * viewport_height;
-        let pixel_delta_u = &viewport_u / WIDTH as f32;
-        let pixel_delta_v = &viewport_v / HEIGHT as f32;
+        let pixel_delta_u = &viewport_u / width as f32;
+        let pixel_delta_v = &viewport_v / height as f32;
 
-        let upper_left: Vec3 = lookat - (viewport_u / 2.) - (viewport_v / 2.);
-        Self { start: lookfrom.clone(), upper_left, delta_x: pixel_delta_u, delta_y: pixel_delta_v, blur }
+        self.upper_left = &self.lookat - (viewport_u / 2.) - (viewport_v / 2.);
+        self.delta_x = pixel_delta_u;
+        self.delta_y = pixel_delta_v;
+        self.width = width;
+        self.height = height;
     }
 
-    pub fn shoot(&self, ux: f32, uy: f32) -> Ray {
-        let mut count = 0;
-        let mut rand_disk_x: f32;
-        let mut rand_disk_y: f32;
-        loop {
-            //needed for focus and blur
-            rand_disk_x = rand::thread_rng().gen_range(-1.0..1.0);
-            rand_disk_y = rand::thread_rng().gen_range(-1.0..1.0);
-            if rand_disk_x * rand_disk_x + rand_disk_y * rand_disk_y <= 1. || count > 50{
-                break
-            }
-            count += 1;
-        }
-        let disk = &self.start  + (rand_disk_x * self.blur) * &self.delta_x + (rand_disk_y * self.blur) * &self.delta_y;
+    //lens is uniform in 0-1 and picks the point on the aperture, needed for focus and blur
+    pub fn shoot(&self, ux: f32, uy: f32, lens: (f32, f32)) -> Ray {
+        let (disk_x, disk_y) = sample_disk(lens);
+        let disk = &self.start  + (disk_x * self.blur) * &self.delta_x + (disk_y * self.blur) * &self.delta_y;
         let target = &self.upper_left + (ux * &self.delta_x) + (uy * &self.delta_y);
         let dir = (&target-&self.start).normalize();
         Ray { start: disk, dir }
@@ -185,6 +263,6 @@ impl Camera {
 
 impl Default for Camera {
     fn default() -> Self {
-        Self::new(&Vec3::new(0., -5., 0.), &Vec3::default(), 90., &Vec3::new(0., 0., 1.), 0.)
+        Self::new(&Vec3::new(0., -5., 0.), &Vec3::default(), 90., &Vec3::new(0., 0., 1.), 0., DEFAULT_WIDTH, DEFAULT_HEIGHT)
     }
 }
\ No newline at end of file
@@ -3,35 +3,108 @@ pub mod render;
 pub mod vec3;
 pub mod textures;
 pub mod objects;
+pub mod bvh;
+pub mod mesh;
+pub mod transform;
+pub mod color;
+pub mod output;
+pub mod lights;
+pub mod sampler;
+pub mod bsdf;
+pub mod spectrum;
+pub mod medium;
+pub mod tiles;
+pub mod checkpoint;
+pub mod distributed;
+pub mod scene_file;
 pub use geometry::Camera;
 pub use objects::Object;
 pub use vec3::Vec3;
+use lights::Lights;
+use medium::{Atmosphere, Medium};
+use sampler::SamplerKind;
+use tiles::TileOrder;
 
-pub const WIDTH: usize = 500;
-pub const HEIGHT: usize = 500;
+pub const DEFAULT_WIDTH: usize = 500;
+pub const DEFAULT_HEIGHT: usize = 500;
+
+pub type EnvShader = Box<dyn Fn(&Vec3) ->Vec3+Send+Sync>;
+
+//sampling of a pixel stops early once the relative standard error of its brightness is below threshold,
+//sample_count is then the most samples a pixel can get
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Adaptive {
+    pub threshold: f32,
+    //samples every pixel gets before the error is trusted
+    pub min_sample_count: u32,
+}
+
+impl Default for Adaptive {
+    fn default() -> Self {
+        Self { threshold: 0.01, min_sample_count: 16 }
+    }
+}
 
 pub struct Uniforms {
     pub sample_count: u32,
+    //None takes sample_count samples in every pixel
+    pub adaptive: Option<Adaptive>,
     pub bounce_count: u8,
+    //bounces every path makes before russian roulette may end it
+    pub min_bounce_count: u8,
+    //where the random numbers of pixel, lens, light and scatter sampling come from
+    pub sampler: SamplerKind,
+    //renders with the same seed are identical
+    pub seed: u64,
+    //follow every path at a few wavelengths instead of in rgb, for dispersion
+    pub spectral: bool,
+    //a medium that fills the space outside of every Volume, like haze over the whole scene
+    pub atmosphere: Option<Atmosphere>,
+    //the image is rendered in squares of tile_size pixels, handed to the threads in tile_order
+    pub tile_size: usize,
+    pub tile_order: TileOrder,
     pub offset: f32,
     pub cam: Camera,
     pub objects: Vec<Object>,
-    pub env_shader: Box<dyn Fn(&Vec3) ->Vec3+Send+Sync>
+    pub env_shader: EnvShader,
+    //filled by prepare, every emissive Sphere and Quad
+    pub lights: Lights,
+    //filled by prepare, the medium the camera rays start in
+    pub camera_medium: Option<Medium>
 }
 
 impl Default for Uniforms {
     fn default() -> Self {
         let func = |v: &Vec3| Vec3::lerp(&Vec3::new1(1.),&Vec3::new(0.5, 0.5, 0.95), (v.dot(&(Vec3::up()*-1.)).max(0.)).abs());
-        Self { sample_count: 100, bounce_count: 50, offset: WIDTH as f32/1000., cam: Camera::default(), objects: vec![], env_shader: Box::new(func) }
+        Self { sample_count: 100, adaptive: None, bounce_count: 50, min_bounce_count: 3, sampler: SamplerKind::default(), seed: 0, spectral: false, atmosphere: None, tile_size: 32, tile_order: TileOrder::default(), offset: DEFAULT_WIDTH as f32/1000., cam: Camera::default(), objects: vec![], env_shader: Box::new(func), lights: Lights::default(), camera_medium: None }
     }
 }    
 
 
 impl Uniforms {
-    pub fn new(sample_count: u32, bounce_count: u8, offset: f32, cam: Camera, objects: Vec<Object>, env_shader: Box<dyn Fn(&Vec3)->Vec3 + Send + Sync>) -> Self {
-        Self {sample_count, bounce_count, offset, cam, objects, env_shader}
+    pub fn new(sample_count: u32, bounce_count: u8, offset: f32, cam: Camera, objects: Vec<Object>, env_shader: EnvShader) -> Self {
+        Self {sample_count, adaptive: None, bounce_count, min_bounce_count: 3, sampler: SamplerKind::default(), seed: 0, spectral: false, atmosphere: None, tile_size: 32, tile_order: TileOrder::default(), offset, cam, objects, env_shader, lights: Lights::default(), camera_medium: None}
+    }
+    //changes the output image size, offset is in pixels and stays the same
+    pub fn set_resolution(&mut self, width: usize, height: usize) {
+        self.cam.set_resolution(width, height);
+    }
+    //collects the lights and builds the bvh, needs to run before rendering
+    pub fn prepare(&mut self) {
+        self.lights = Lights::collect(&self.objects);
+        self.build_bvh();
+        self.camera_medium = Object::medium_at_camera(self);
+    }
+
+    //replaces the object list with a single bounding volume hierarchy over all of it
+    pub fn build_bvh(&mut self) {
+        if let [Object::Bvh(_)] = self.objects.as_slice() {
+            return;
+        }
+        let objs = std::mem::take(&mut self.objects);
+        self.objects = vec![Object::new_bvh(objs)];
     }
-    pub fn get_env_shader() -> Box<dyn Fn(&Vec3)->Vec3+Sync+Send>{
+    pub fn get_env_shader() -> EnvShader{
         let clos = |v: &Vec3| Vec3::lerp(&Vec3::new1(0.8),&Vec3::new(0.5, 0.7, 1.), v.dot(&Vec3::up()).max(0.));
         Box::new(clos)
     }
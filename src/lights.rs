@@ -0,0 +1,234 @@
+use std::f32::consts::PI;
+use crate::{Vec3, Object, objects::QuadType};
+
+//the shape of an emissive Sphere or Quad, used to send rays straight towards it
+#[derive(Clone, Debug)]
+pub enum LightShape {
+    Sphere {pos: Vec3, rad: f32},
+    Quad {pos: Vec3, delta_x: Vec3, delta_y: Vec3, kind: QuadType, normal: Vec3},
+}
+
+#[derive(Clone, Debug)]
+pub struct Light {
+    pub shape: LightShape,
+    pub emission: Vec3,
+}
+
+pub struct LightSample {
+    pub dir: Vec3,
+    pub dist: f32,
+    pub radiance: Vec3,
+    //per solid angle, seen from the shaded point
+    pub pdf: f32,
+}
+
+pub fn luminance(c: &Vec3) -> f32 {
+    0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z
+}
+
+//1 - cos of the cone half angle, sin2 is its squared sine, stays accurate for tiny and far spheres
+fn cone_size(sin2: f32) -> f32 {
+    sin2 / (1. + (1. - sin2).max(0.).sqrt())
+}
+
+impl Light {
+    pub fn area(&self) -> f32 {
+        match &self.shape {
+            LightShape::Sphere { rad, .. } => 4. * PI * rad * rad,
+            LightShape::Quad { delta_x, delta_y, kind, .. } => {
+                let parallelogram = delta_x.cross(delta_y).length();
+                match kind {
+                    QuadType::Rect() => parallelogram,
+                    QuadType::Triangle() => parallelogram / 2.,
+                    QuadType::Disk() => parallelogram * PI,
+                }
+            }
+        }
+    }
+
+    pub fn power(&self) -> f32 {
+        luminance(&self.emission).max(0.) * self.area()
+    }
+
+    //u1 and u2 are uniform in 0-1, None if p can't see the light
+    pub fn sample(&self, p: &Vec3, u1: f32, u2: f32) -> Option<LightSample> {
+        match &self.shape {
+            //uniform sampling of the cone the sphere covers, https://pbr-book.org/3ed-2018/Light_Transport_I_Surface_Reflection/Sampling_Light_Sources
+            LightShape::Sphere { pos, rad } => {
+                let to_center = pos - p;
+                let dist2 = to_center.length_squared();
+                if dist2 <= rad * rad {
+                    return None;
+                }
+                let one_minus_cos_max = cone_size(rad * rad / dist2);
+                let cos_theta = 1. - u1 * one_minus_cos_max;
+                let sin_theta = (1. - cos_theta * cos_theta).max(0.).sqrt();
+                let phi = 2. * PI * u2;
+                let axis = to_center.normalize();
+                let (bx, by) = axis.orthonormal_basis();
+                let dir = (bx * (sin_theta * phi.cos()) + by * (sin_theta * phi.sin()) + &axis * cos_theta).normalize();
+                //nearest intersection with the sphere along dir
+                let proj = to_center.dot(&dir);
+                let dist = proj - (rad * rad - (dist2 - proj * proj)).max(0.).sqrt();
+                Some(LightSample { dir, dist, radiance: self.emission.clone(), pdf: 1. / (2. * PI * one_minus_cos_max) })
+            }
+            LightShape::Quad { pos, delta_x, delta_y, kind, normal } => {
+                let (a, b) = match kind {
+                    QuadType::Rect() => (u1, u2),
+                    QuadType::Triangle() if u1 + u2 > 1. => (1. - u1, 1. - u2),
+                    QuadType::Triangle() => (u1, u2),
+                    QuadType::Disk() => {
+                        let r = u1.sqrt();
+                        (r * (2. * PI * u2).cos(), r * (2. * PI * u2).sin())
+                    }
+                };
+                let q = pos + delta_x * a + delta_y * b;
+                let to_light = &q - p;
+                let dist = to_light.length();
+                let dir = to_light / dist;
+                if normal.dot(&dir).abs() < 1e-6 {
+                    return None;
+                }
+                Some(LightSample { dir, dist, radiance: self.emission.clone(), pdf: self.pdf(p, &q) })
+            }
+        }
+    }
+
+    //the pdf sample would have for the direction from p to the point on_light, per solid angle
+    pub fn pdf(&self, p: &Vec3, on_light: &Vec3) -> f32 {
+        match &self.shape {
+            LightShape::Sphere { pos, rad } => {
+                let dist2 = (pos - p).length_squared();
+                if dist2 <= rad * rad {
+                    return 0.;
+                }
+                1. / (2. * PI * cone_size(rad * rad / dist2))
+            }
+            LightShape::Quad { normal, .. } => {
+                let to_light = on_light - p;
+                let dist2 = to_light.length_squared();
+                let cos_light = normal.dot(&to_light).abs() / dist2.sqrt();
+                if cos_light < 1e-6 {
+                    return 0.;
+                }
+                dist2 / (self.area() * cos_light)
+            }
+        }
+    }
+}
+
+//every emissive Sphere and Quad of the scene, picked proportional to their power
+#[derive(Default)]
+pub struct Lights {
+    pub lights: Vec<Light>,
+    cdf: Vec<f32>,
+    total_power: f32,
+}
+
+impl Lights {
+    pub fn new(lights: Vec<Light>) -> Self {
+        let mut cdf: Vec<f32> = vec![];
+        let mut total_power = 0.;
+        for l in &lights {
+            total_power += l.power();
+            cdf.push(total_power);
+        }
+        Self { lights, cdf, total_power }
+    }
+
+    //objects under a Transform and mesh triangles are left out, they are only found by chance
+    pub fn collect(objs: &[Object]) -> Self {
+        let mut lights: Vec<Light> = vec![];
+        Self::collect_into(objs, &mut lights);
+        Self::new(lights.into_iter().filter(|l| l.power() > 0.).collect())
+    }
+
+    fn collect_into<'a>(objs: impl IntoIterator<Item = &'a Object>, lights: &mut Vec<Light>) {
+        for obj in objs {
+            match obj {
+                Object::BoundBox { inside, .. } => Self::collect_into(inside, lights),
+                Object::Bvh(bvh) => Self::collect_into(bvh.objects(), lights),
+                obj => lights.extend(obj.light()),
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    //the chance of pick returning this light
+    pub fn selection_pdf(&self, light: &Light) -> f32 {
+        if self.total_power <= 0. {
+            return 0.;
+        }
+        light.power() / self.total_power
+    }
+
+    //u is uniform in 0-1
+    pub fn pick(&self, u: f32) -> Option<(&Light, f32)> {
+        if self.lights.is_empty() {
+            return None;
+        }
+        let target = u * self.total_power;
+        let index = self.cdf.partition_point(|c| *c <= target).min(self.lights.len() - 1);
+        let light = &self.lights[index];
+        Some((light, self.selection_pdf(light)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //the solid angle the light covers from p, as the mean of 1 / pdf over a grid of samples
+    fn solid_angle(light: &Light, p: &Vec3) -> f32 {
+        let n = 200;
+        let mut sum = 0.;
+        for i in 0..n {
+            for j in 0..n {
+                let s = light.sample(p, (i as f32 + 0.5) / n as f32, (j as f32 + 0.5) / n as f32).unwrap();
+                let pdf = light.pdf(p, &(p + &s.dir * s.dist));
+                assert!((s.pdf - pdf).abs() <= 1e-3 * pdf, "sampled with pdf {} but pdf gives {}", s.pdf, pdf);
+                sum += 1. / s.pdf;
+            }
+        }
+        sum / (n * n) as f32
+    }
+
+    #[test]
+    fn light_pdfs_match_their_solid_angle() {
+        let sphere = Light { shape: LightShape::Sphere { pos: Vec3::new(0., 2., 0.), rad: 1. }, emission: Vec3::new1(1.) };
+        //a cone with a half angle of 30 degrees
+        let cone = 2. * PI * (1. - 0.75f32.sqrt());
+        let got = solid_angle(&sphere, &Vec3::default());
+        assert!((got - cone).abs() < 1e-3, "sphere covers {} instead of {}", got, cone);
+
+        let (delta_x, delta_y) = (Vec3::new(1., 0., 0.), Vec3::new(0., 0., 1.));
+        let normal = delta_x.cross(&delta_y).normalize();
+        let quad = Light { shape: LightShape::Quad { pos: Vec3::new(-0.5, 1., -0.5), delta_x, delta_y, kind: QuadType::Rect(), normal }, emission: Vec3::new1(1.) };
+        //a unit square centered at distance 1
+        let square = 4. * 0.2f32.asin();
+        let got = solid_angle(&quad, &Vec3::default());
+        assert!((got - square).abs() < 1e-3, "quad covers {} instead of {}", got, square);
+    }
+
+    #[test]
+    fn lights_are_picked_by_their_power() {
+        let light = |emission: f32| Light { shape: LightShape::Sphere { pos: Vec3::default(), rad: 1. }, emission: Vec3::new1(emission) };
+        let lights = Lights::new(vec![light(1.), light(3.), light(0.), light(4.)]);
+        let n = 8000;
+        let mut picked = [0; 4];
+        for i in 0..n {
+            let (light, pdf) = lights.pick((i as f32 + 0.5) / n as f32).unwrap();
+            let index = lights.lights.iter().position(|l| std::ptr::eq(l, light)).unwrap();
+            assert_eq!(pdf, lights.selection_pdf(light));
+            picked[index] += 1;
+        }
+        for (light, count) in lights.lights.iter().zip(picked) {
+            let share = count as f32 / n as f32;
+            assert!((share - lights.selection_pdf(light)).abs() < 1e-3, "picked {} of the time instead of {}", share, lights.selection_pdf(light));
+        }
+        assert_eq!(picked[2], 0, "a light without power was picked");
+    }
+}
@@ -2,50 +2,301 @@
 
 use raytracing::{
     Uniforms,
+    Adaptive,
     geometry::*,
     render::*,
     vec3::Vec3,
     textures::*,
     objects::*,
+    color::ColorSpace,
+    output::{HdrFormat, RenderPass, save_hdr},
+    checkpoint::Checkpoint,
+    distributed::{self, Message, Worker},
+    tiles,
 };
 
 mod scenes;
-use scenes::{lalaland, cylinder_test};
+mod cli;
+use cli::{Command, WorkerAddr};
 use image::RgbImage;
 use image::{ImageBuffer, ImageReader};
 use rand::Rng;
 use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::process;
 
 
 //https://raytracing.github.io/books/RayTracingInOneWeekend.html
 
 
-//fragment shader -> runs for every pixel
-fn frag(x: usize, y: usize, input: &Uniforms) -> Pixel {
-    let mut rng = rand::thread_rng();
-    let mut color_sum = Vec3::default();
-    for _ in 0..input.sample_count {
-        let mut rand_x: f32 = 0.;
-        let mut rand_y: f32 = 0.;
-        if input.offset != 0. {
-            rand_x = rng.gen_range(-input.offset..input.offset);
-            rand_y = rng.gen_range(-input.offset..input.offset);
+//fragment shader -> runs for every pixel, adds samples to stats until it holds `until` of them
+fn frag(x: usize, y: usize, input: &Uniforms, stats: &mut PixelStats, until: u32) {
+    let mut sampler = input.sampler.create(input.sample_count, input.seed);
+    while stats.count < until {
+        if let Some(adaptive) = input.adaptive {
+            if stats.count >= adaptive.min_sample_count && stats.relative_error() < adaptive.threshold {
+                break;
+            }
         }
-        let ray = input.cam.shoot(x as f32 + rand_x, y as f32 + rand_y);
-        color_sum = color_sum + Object::bounce(&ray, &input.objects, input.bounce_count, &input.env_shader);
+        sampler.start_sample(x, y, stats.count);
+        let (jitter_x, jitter_y) = sampler.get_2d();
+        let lens = sampler.get_2d();
+        let rand_x = (2. * jitter_x - 1.) * input.offset;
+        let rand_y = (2. * jitter_y - 1.) * input.offset;
+        let ray = input.cam.shoot(x as f32 + rand_x, y as f32 + rand_y, lens);
+        stats.add(&Object::bounce(&ray, input, sampler.as_mut()));
     }
-    //average of color samples
-    let avg_color = color_sum / input.sample_count as f32;
-    Pixel::from_vec(avg_color)
+}
+
+//cli::parse checked the extensions, an image that still can't be written (e.g. a missing directory) ends the program
+fn saved<E: fmt::Display>(result: Result<(), E>, path: &Path) {
+    if let Err(err) = result {
+        eprintln!("error: could not save {}: {}", path.display(), err);
+        process::exit(1);
+    }
+}
+
+//saves the image and the sample map, also used for the snapshots of progressive renders
+fn save_frame(opts: &cli::Options, frame: &Frame, adaptive: bool) -> Picture {
+    let color = frame.color();
+    let pic = color.to_picture(ColorSpace::Srgb);
+    //let pic = Picture::new(ImageReader::open("sample2.png").unwrap().decode().unwrap().to_rgb8());
+    //exr files also get the sample counts of adaptive renders
+    let passes = if adaptive { vec![RenderPass::mono("samples", frame.samples())] } else { vec![] };
+    match HdrFormat::from_path(&opts.output) {
+        Some(format) => saved(save_hdr(&opts.output, format, &color, &passes), &opts.output),
+        None => saved(pic.to_buffer().save(&opts.output), &opts.output),
+    }
+    if let Some(path) = &opts.sample_map {
+        match HdrFormat::from_path(path) {
+            Some(format) => saved(save_hdr(path, format, &frame.samples(), &[]), path),
+            //the pixel with the most samples is white
+            None => saved(frame.samples().normalized().to_picture(ColorSpace::Linear).to_buffer().save(path), path),
+        }
+    }
+    pic
+}
+
+//the scene a coordinator sent to a worker
+fn worker_scene(args: &[String], scene_source: Option<&str>) -> Result<Uniforms, String> {
+    match cli::parse(args)? {
+        Command::Render(opts) => cli::load_scene_from(&opts, scene_source),
+        _ => Err("the coordinator sent no render command".to_string()),
+    }
+}
+
+//serves one coordinator after the other
+fn listen(addr: &str) {
+    let listener = match TcpListener::bind(addr) {
+        Ok(listener) => listener,
+        Err(err) => {
+            eprintln!("error: could not listen on {}: {}", addr, err);
+            process::exit(1);
+        }
+    };
+    eprintln!("waiting for coordinators on {}", addr);
+    for stream in listener.incoming() {
+        let served = stream.and_then(|stream| {
+            let reader = stream.try_clone()?;
+            distributed::serve(reader, stream, worker_scene, frag)
+        });
+        if let Err(err) = served {
+            eprintln!("worker error: {}", err);
+        }
+    }
+}
+
+//starts the local workers, connects to the remote ones and renders the tiles of the image on them
+fn render_distributed(opts: &cli::Options, args: &[String], uni: &Uniforms) -> Result<Frame, String> {
+    let mut children: Vec<process::Child> = vec![];
+    let mut workers: Vec<Worker> = vec![];
+    for (i, addr) in opts.workers.iter().enumerate() {
+        match addr {
+            WorkerAddr::Local => {
+                let exe = env::current_exe().map_err(|e| format!("could not find the executable: {}", e))?;
+                let mut child = process::Command::new(exe).arg("--worker")
+                    .stdin(process::Stdio::piped())
+                    .stdout(process::Stdio::piped())
+                    .spawn()
+                    .map_err(|e| format!("could not start a local worker: {}", e))?;
+                let reader = child.stdout.take().expect("worker stdout is piped");
+                let writer = child.stdin.take().expect("worker stdin is piped");
+                workers.push(Worker { name: format!("local {}", i), reader: Box::new(reader), writer: Box::new(writer) });
+                children.push(child);
+            }
+            WorkerAddr::Tcp(addr) => {
+                let stream = TcpStream::connect(addr).map_err(|e| format!("could not connect to worker {}: {}", addr, e))?;
+                let reader = stream.try_clone().map_err(|e| e.to_string())?;
+                workers.push(Worker { name: addr.clone(), reader: Box::new(reader), writer: Box::new(stream) });
+            }
+        }
+    }
+    let scene_source = match &opts.scene_file {
+        Some(path) => Some(fs::read_to_string(path).map_err(|e| format!("could not read {}: {}", path.display(), e))?),
+        None => None,
+    };
+    let (width, height) = (uni.cam.width, uni.cam.height);
+    let tiles = tiles::tiles(width, height, uni.tile_size, uni.tile_order);
+    println!("Rendering image on {} workers...", workers.len());
+    let frame = distributed::coordinate(workers, Message::Scene { args: args.to_vec(), scene_source }, width, height, tiles);
+    for mut child in children {
+        let _ = child.wait();
+    }
+    println!("done!");
+    frame
+}
+
+//how a render sampled, for the errors of check_resume
+fn describe_adaptive(adaptive: &Option<Adaptive>) -> String {
+    match adaptive {
+        Some(a) => format!("--adaptive {} --min-samples {}", a.threshold, a.min_sample_count),
+        None => "no --adaptive".to_string(),
+    }
+}
+
+//a checkpoint only continues the render it was saved from, render is the checkpoint this one would save
+//without its pixels, -n may raise the sample count
+fn check_resume(checkpoint: &Checkpoint, render: &Checkpoint, opts: &cli::Options, uni: &Uniforms) -> Result<(), String> {
+    let (width, height) = (checkpoint.frame.width, checkpoint.frame.height);
+    if (width, height) != (uni.cam.width, uni.cam.height) {
+        return Err(format!("it is {}x{} but the scene renders at {}x{}", width, height, uni.cam.width, uni.cam.height));
+    }
+    if checkpoint.scene != render.scene {
+        return Err(format!("it is of the scene {} but this render is of {}", checkpoint.scene, render.scene));
+    }
+    if checkpoint.scene_hash != render.scene_hash {
+        return Err(format!("{} changed after the checkpoint was saved", render.scene));
+    }
+    if checkpoint.seed != render.seed {
+        return Err(format!("it was rendered with --seed {}, this render uses {}", checkpoint.seed, render.seed));
+    }
+    if checkpoint.sampler != render.sampler {
+        return Err(format!("it was rendered with --sampler {}, this render uses {}", checkpoint.sampler.name(), render.sampler.name()));
+    }
+    if checkpoint.spectral != render.spectral {
+        return Err(format!("it was rendered {}", if checkpoint.spectral { "with --spectral" } else { "without --spectral" }));
+    }
+    if checkpoint.bounce_count != render.bounce_count {
+        return Err(format!("it was rendered with -b {}, this render uses {}", checkpoint.bounce_count, render.bounce_count));
+    }
+    if checkpoint.min_bounce_count != render.min_bounce_count {
+        return Err(format!("it was rendered with --min-bounces {}, this render uses {}", checkpoint.min_bounce_count, render.min_bounce_count));
+    }
+    if checkpoint.adaptive != render.adaptive {
+        return Err(format!("it was rendered with {}, this render uses {}", describe_adaptive(&checkpoint.adaptive), describe_adaptive(&render.adaptive)));
+    }
+    if checkpoint.atmosphere != render.atmosphere {
+        return Err("it was rendered with another atmosphere".to_string());
+    }
+    let raised = opts.sample_count.is_some() && render.sample_count > checkpoint.sample_count;
+    if checkpoint.sample_count != render.sample_count && !raised {
+        return Err(format!("it was rendered with -n {}, this render uses {}", checkpoint.sample_count, render.sample_count));
+    }
+    Ok(())
 }
 
 fn main() {
     env::set_var("RUST_BACKTRACE", "1");
-    let mut uni = scenes::lalaland();
-    uni.sample_count = 50;
-    let pic = display(frag, uni);
-    //let pic = Picture::new(ImageReader::open("sample2.png").unwrap().decode().unwrap().to_rgb8());
-    pic.to_buffer().save("sample.png").expect("an error occured while saving the image");
-    pic.denoise(20).to_buffer().save("sample2.png").expect("an error occured while saving the image");
-    
+    let args: Vec<String> = env::args().skip(1).collect();
+    let opts = match cli::parse(&args) {
+        Ok(Command::Render(opts)) => *opts,
+        Ok(Command::Help) => {
+            println!("{}", cli::USAGE);
+            return;
+        }
+        Ok(Command::Worker) => {
+            if let Err(err) = distributed::serve(io::stdin(), io::stdout(), worker_scene, frag) {
+                eprintln!("worker error: {}", err);
+                process::exit(1);
+            }
+            return;
+        }
+        Ok(Command::Listen(addr)) => {
+            listen(&addr);
+            return;
+        }
+        Err(msg) => {
+            eprintln!("error: {}\n\n{}", msg, cli::USAGE);
+            process::exit(2);
+        }
+    };
+    let uni = match cli::load_scene(&opts) {
+        Ok(uni) => uni,
+        Err(msg) => {
+            eprintln!("error: {}", msg);
+            process::exit(1);
+        }
+    };
+    let scene_hash = cli::scene_hash(&opts).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    });
+    //the checkpoint this render saves, without its pixels
+    let settings = Checkpoint {
+        scene: cli::scene_name(&opts),
+        scene_hash,
+        sample_count: uni.sample_count,
+        seed: uni.seed,
+        sampler: uni.sampler,
+        spectral: uni.spectral,
+        bounce_count: uni.bounce_count,
+        min_bounce_count: uni.min_bounce_count,
+        adaptive: uni.adaptive,
+        atmosphere: uni.atmosphere.clone(),
+        frame: Frame::new(0, 0),
+    };
+    //a resumed render continues the sample sequence of the checkpoint
+    let start = opts.resume.as_ref().map(|path| {
+        let checkpoint = Checkpoint::load(path).unwrap_or_else(|err| {
+            eprintln!("error: could not resume from {}: {}", path.display(), err);
+            process::exit(1);
+        });
+        if let Err(msg) = check_resume(&checkpoint, &settings, &opts, &uni) {
+            eprintln!("error: can not resume from {}: {}", path.display(), msg);
+            process::exit(1);
+        }
+        checkpoint.frame
+    });
+    let adaptive = uni.adaptive.is_some();
+    //a checkpoint that can't be written doesn't stop the render, the next save may work
+    let save_checkpoint = |frame: &Frame| {
+        if let Some(path) = &opts.checkpoint {
+            let checkpoint = Checkpoint { frame: frame.clone(), ..settings.clone() };
+            if let Err(err) = checkpoint.save(path) {
+                eprintln!("error: could not save the checkpoint {}: {}", path.display(), err);
+            }
+        }
+    };
+    let frame = if !opts.workers.is_empty() {
+        match render_distributed(&opts, &args, &uni) {
+            Ok(frame) => frame,
+            Err(msg) => {
+                eprintln!("error: {}", msg);
+                process::exit(1);
+            }
+        }
+    } else if opts.progressive.is_some() || opts.checkpoint.is_some() || start.is_some() {
+        //without --progressive the whole render is one pass, the checkpoints are written every few minutes
+        let progressive = Progressive {
+            pass_size: opts.progressive.unwrap_or(uni.sample_count),
+            interval: opts.snapshot_interval.or(if opts.progressive.is_some() { None } else { Some(cli::CHECKPOINT_INTERVAL) }),
+        };
+        display_progressive(frag, uni, &progressive, start, |frame| {
+            if opts.progressive.is_some() {
+                save_frame(&opts, frame, adaptive);
+            }
+            save_checkpoint(frame);
+        })
+    } else {
+        display(frag, uni)
+    };
+    save_checkpoint(&frame);
+    let pic = save_frame(&opts, &frame, adaptive);
+    if opts.denoise {
+        saved(pic.denoise(opts.denoise_radius).to_buffer().save(&opts.denoise_output), &opts.denoise_output);
+    }
 }
@@ -0,0 +1,168 @@
+use std::f32::consts::PI;
+use crate::{Vec3, bsdf::{Bsdf, BsdfSample}, sampler::Sampler, spectrum::Wavelengths};
+
+//fog or smoke with the same density everywhere, the coefficients are per unit of distance
+#[derive(Clone, Debug, PartialEq)]
+pub struct Medium {
+    pub absorption: Vec3,
+    pub scattering: Vec3,
+    //henyey-greenstein asymmetry in -1..1, below 0 light is scattered back, above 0 forward
+    pub g: f32,
+}
+
+impl Medium {
+    //the coefficients at the wavelengths of a spectral path
+    pub fn at(&self, wavelengths: Option<&Wavelengths>) -> Self {
+        match wavelengths {
+            Some(w) => Self { absorption: w.upsample(&self.absorption), scattering: w.upsample(&self.scattering), g: self.g },
+            None => self.clone(),
+        }
+    }
+
+    fn extinction(&self) -> Vec3 {
+        &self.absorption + &self.scattering
+    }
+
+    //the fraction of light that passes dist without being absorbed or scattered away
+    pub fn transmittance(&self, dist: f32) -> Vec3 {
+        let e = self.extinction();
+        Vec3::new((-e.x * dist).exp(), (-e.y * dist).exp(), (-e.z * dist).exp())
+    }
+
+    //picks how far a ray gets before it scatters, None if it passes all of dist,
+    //the weight is the transmittance (and scattering) over the pdf of the choice
+    //the distance is sampled for one of the color channels, the pdf is the average of all three
+    pub fn sample_distance(&self, dist: f32, u: (f32, f32)) -> (Option<f32>, Vec3) {
+        let e = self.extinction();
+        let channel = [e.x, e.y, e.z][((u.0 * 3.) as usize).min(2)];
+        let t = if channel > 0. { -(1. - u.1).ln() / channel } else { f32::INFINITY };
+        if t < dist {
+            let tr = self.transmittance(t);
+            let density = &e * &tr;
+            let pdf = (density.x + density.y + density.z) / 3.;
+            if pdf <= 0. {
+                return (Some(t), Vec3::default());
+            }
+            (Some(t), tr * &self.scattering / pdf)
+        } else {
+            let tr = self.transmittance(dist);
+            let pdf = (tr.x + tr.y + tr.z) / 3.;
+            if pdf <= 0. {
+                return (None, Vec3::default());
+            }
+            (None, tr / pdf)
+        }
+    }
+}
+
+//a medium that fills the scene outside of every Volume
+#[derive(Clone, Debug, PartialEq)]
+pub struct Atmosphere {
+    pub medium: Medium,
+    //how far rays that leave the scene travel through it before they reach the environment
+    pub depth: f32,
+}
+
+impl Atmosphere {
+    pub const DEFAULT_DEPTH: f32 = 100.;
+}
+
+//where a ray crosses the boundary of a Volume
+#[derive(Clone, Debug)]
+pub struct Interface {
+    pub medium: Medium,
+    //the ray was inside the volume before the crossing
+    pub leaving: bool,
+}
+
+//the henyey-greenstein phase function, it scatters like a bsdf that ignores the normal
+//https://www.pbr-book.org/3ed-2018/Volume_Scattering/Phase_Functions
+pub struct HenyeyGreenstein {
+    pub g: f32,
+}
+
+impl HenyeyGreenstein {
+    //density of scattering into a direction at cos to the incoming one, per solid angle
+    fn phase(&self, cos: f32) -> f32 {
+        let g = self.g;
+        let d = 1. + g * g - 2. * g * cos;
+        (1. - g * g) / (4. * PI * d * d.max(1e-8).sqrt())
+    }
+}
+
+impl Bsdf for HenyeyGreenstein {
+    fn sample(&self, incoming: &Vec3, _normal: &Vec3, sampler: &mut dyn Sampler) -> Option<BsdfSample> {
+        let (u1, u2) = sampler.get_2d();
+        let g = self.g;
+        let cos = if g.abs() < 1e-3 {
+            1. - 2. * u1
+        } else {
+            let s = (1. - g * g) / (1. - g + 2. * g * u1);
+            ((1. + g * g - s * s) / (2. * g)).clamp(-1., 1.)
+        };
+        let sin = (1. - cos * cos).max(0.).sqrt();
+        let phi = 2. * PI * u2;
+        let (t, b) = incoming.orthonormal_basis();
+        let dir = (t * (sin * phi.cos()) + b * (sin * phi.sin()) + incoming * cos).normalize();
+        //the phase function is sampled exactly, the weight is 1
+        Some(BsdfSample { dir, weight: Vec3::new1(1.), pdf: self.phase(cos), specular: false })
+    }
+
+    fn eval(&self, incoming: &Vec3, _normal: &Vec3, dir: &Vec3) -> Vec3 {
+        Vec3::new1(self.phase(incoming.dot(dir)))
+    }
+
+    fn pdf(&self, incoming: &Vec3, _normal: &Vec3, dir: &Vec3) -> f32 {
+        self.phase(incoming.dot(dir))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::SamplerKind;
+
+    #[test]
+    fn distances_are_weighed_to_the_light_each_channel_gets() {
+        let m = Medium { absorption: Vec3::new(0.1, 0.5, 0.), scattering: Vec3::new(0.4, 0.2, 1.5), g: 0. };
+        let dist = 2.;
+        let n = 1000;
+        let (mut passed, mut scattered) = (Vec3::default(), Vec3::default());
+        for i in 0..n {
+            for j in 0..n {
+                match m.sample_distance(dist, ((i as f32 + 0.5) / n as f32, (j as f32 + 0.5) / n as f32)) {
+                    (Some(_), weight) => scattered = scattered + weight,
+                    (None, weight) => passed = passed + weight,
+                }
+            }
+        }
+        let count = (n * n) as f32;
+        let (passed, scattered) = (passed / count, scattered / count);
+        //the light that gets through, and the scattering along the way, which adds up to scattering / extinction of what stays
+        let tr = m.transmittance(dist);
+        let e = m.extinction();
+        let expected = Vec3::new(m.scattering.x / e.x * (1. - tr.x), m.scattering.y / e.y * (1. - tr.y), m.scattering.z / e.z * (1. - tr.z));
+        assert!((&passed - &tr).length() < 1e-2, "{:?} passed instead of {:?}", passed, tr);
+        assert!((&scattered - &expected).length() < 1e-2, "{:?} scattered instead of {:?}", scattered, expected);
+    }
+
+    #[test]
+    fn henyey_greenstein_samples_its_phase() {
+        let incoming = Vec3::new(0., 0.6, 0.8);
+        let n = 20000;
+        for g in [-0.6, 0., 0.3, 0.9] {
+            let phase = HenyeyGreenstein { g };
+            let mut sampler = SamplerKind::Independent.create(n, 1);
+            let mut mean_cos = 0.;
+            for i in 0..n {
+                sampler.start_sample(0, 0, i);
+                let s = phase.sample(&incoming, &incoming, sampler.as_mut()).unwrap();
+                let pdf = phase.pdf(&incoming, &incoming, &s.dir);
+                assert!((s.pdf - pdf).abs() <= 1e-3 * pdf, "sampled with pdf {} but pdf gives {}", s.pdf, pdf);
+                mean_cos += incoming.dot(&s.dir) / n as f32;
+            }
+            //g is the mean cosine of the scattering angle
+            assert!((mean_cos - g).abs() < 0.02, "g {} scatters with a mean cosine of {}", g, mean_cos);
+        }
+    }
+}
@@ -0,0 +1,385 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use image::ImageReader;
+use crate::{
+    Vec3, Object,
+    bvh::Aabb,
+    geometry::{HitInfo, Material, Ray, Reflection},
+    render::Picture,
+    textures::Texture,
+};
+
+#[derive(Debug)]
+pub enum MeshError {
+    Io { path: PathBuf, err: std::io::Error },
+    Parse { path: PathBuf, line: usize, msg: String },
+}
+
+impl fmt::Display for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { path, err } => write!(f, "could not read {}: {}", path.display(), err),
+            Self::Parse { path, line, msg } => write!(f, "{} line {}: {}", path.display(), line, msg),
+        }
+    }
+}
+
+impl std::error::Error for MeshError {}
+
+#[derive(Clone, Debug)]
+pub struct MeshTriangle {
+    pub vertices: [usize; 3],
+    pub normals: Option<[usize; 3]>,
+    pub uvs: Option<[usize; 3]>,
+    pub mat: usize,
+}
+
+//indexed triangle list, every triangle refers to the shared vertex data
+#[derive(Clone, Debug, Default)]
+pub struct Mesh {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(f32, f32)>,
+    pub triangles: Vec<MeshTriangle>,
+    pub materials: Vec<Material>,
+}
+
+impl Mesh {
+    //every triangle uses the material at its mat index, meshes without materials get Material::default()
+    pub fn into_object(mut self) -> Object {
+        if self.materials.is_empty() {
+            self.materials.push(Material::default());
+        }
+        let count = self.triangles.len();
+        let mesh = Arc::new(self);
+        let tris: Vec<Object> = (0..count).map(|index| Object::Triangle { mesh: mesh.clone(), index }).collect();
+        Object::new_bvh(tris)
+    }
+
+    //replaces every material of the mesh
+    pub fn set_material(&mut self, mat: Material) {
+        self.materials = vec![mat];
+        for tri in &mut self.triangles {
+            tri.mat = 0;
+        }
+    }
+
+    pub fn triangle_bounds(&self, index: usize) -> Aabb {
+        let [a, b, c] = self.triangles[index].vertices;
+        Aabb::from_points(&[self.positions[a].clone(), self.positions[b].clone(), self.positions[c].clone()])
+    }
+
+    //https://en.wikipedia.org/wiki/M%C3%B6ller%E2%80%93Trumbore_intersection_algorithm
+    pub fn intersect_triangle(&self, index: usize, ray: &Ray) -> Option<HitInfo> {
+        let tri = &self.triangles[index];
+        let [a, b, c] = tri.vertices.map(|i| &self.positions[i]);
+        let edge1 = b - a;
+        let edge2 = c - a;
+        let pvec = ray.dir.cross(&edge2);
+        let det = edge1.dot(&pvec);
+        if det.abs() < 1e-8 {
+            return None;
+        }
+        let inv_det = 1. / det;
+        let tvec = &ray.start - a;
+        let beta = tvec.dot(&pvec) * inv_det;
+        if !(0. ..=1.).contains(&beta) {
+            return None;
+        }
+        let qvec = tvec.cross(&edge1);
+        let gamma = ray.dir.dot(&qvec) * inv_det;
+        if gamma < 0. || beta + gamma > 1. {
+            return None;
+        }
+        let t = edge2.dot(&qvec) * inv_det;
+        if t <= 0. {
+            return None;
+        }
+        let alpha = 1. - beta - gamma;
+
+        let geometric = edge1.cross(&edge2).normalize();
+        let mut normal = match tri.normals {
+            Some([na, nb, nc]) => {
+                let n = &self.normals[na] * alpha + &self.normals[nb] * beta + &self.normals[nc] * gamma;
+                if n.length_squared() > 0. { n.normalize() } else { geometric.clone() }
+            }
+            None => geometric.clone(),
+        };
+        if normal.dot(&geometric) < 0. {
+            normal = normal * -1.;
+        }
+        let material = self.materials[tri.mat].clone();
+        //glass needs the outward normal to tell entering and leaving apart, everything else faces the ray like Quad
+        if !material.refl.transmits() && normal.dot(&ray.dir) > 0. {
+            normal = normal * -1.;
+        }
+        let (u, v) = match tri.uvs {
+            Some([ta, tb, tc]) => {
+                let u = self.uvs[ta].0 * alpha + self.uvs[tb].0 * beta + self.uvs[tc].0 * gamma;
+                let v = self.uvs[ta].1 * alpha + self.uvs[tb].1 * beta + self.uvs[tc].1 * gamma;
+                //obj textures repeat and start at the bottom of the image
+                (u.rem_euclid(1.), (1. - v).rem_euclid(1.))
+            }
+            None => (beta, gamma),
+        };
+        Some(HitInfo { p: &ray.start + &ray.dir * t, normal, material, u, v, light: None, interface: None })
+    }
+}
+
+fn parse_err(path: &Path, line: usize, msg: String) -> MeshError {
+    MeshError::Parse { path: path.to_path_buf(), line, msg }
+}
+
+fn floats<const N: usize>(path: &Path, line: usize, toks: &[&str]) -> Result<[f32; N], MeshError> {
+    if toks.len() < N {
+        return Err(parse_err(path, line, format!("expected {} numbers, found {}", N, toks.len())));
+    }
+    let mut sol = [0.; N];
+    for (i, tok) in toks.iter().take(N).enumerate() {
+        sol[i] = tok.parse().map_err(|_| parse_err(path, line, format!("'{}' is not a valid number", tok)))?;
+    }
+    Ok(sol)
+}
+
+//obj indices start at 1, negative ones count back from the last element
+fn resolve_index(path: &Path, line: usize, tok: &str, len: usize) -> Result<usize, MeshError> {
+    let i: i64 = tok.parse().map_err(|_| parse_err(path, line, format!("'{}' is not a valid index", tok)))?;
+    let index = if i < 0 { len as i64 + i } else { i - 1 };
+    if index < 0 || index >= len as i64 {
+        return Err(parse_err(path, line, format!("index {} is out of range, only {} defined so far", i, len)));
+    }
+    Ok(index as usize)
+}
+
+fn read(path: &Path) -> Result<String, MeshError> {
+    std::fs::read_to_string(path).map_err(|err| MeshError::Io { path: path.to_path_buf(), err })
+}
+
+//the rest of the line after the keyword, file names may contain spaces
+fn rest_of_line<'a>(text: &'a str, keyword: &str) -> &'a str {
+    text.trim_start()[keyword.len()..].trim()
+}
+
+fn load_image(path: &Path, images: &mut HashMap<PathBuf, Picture>) -> Result<Picture, String> {
+    if let Some(img) = images.get(path) {
+        return Ok(img.clone());
+    }
+    let decoded = ImageReader::open(path)
+        .map_err(|e| format!("could not open image {}: {}", path.display(), e))?
+        .decode()
+        .map_err(|e| format!("could not decode image {}: {}", path.display(), e))?;
+    let img = Picture::new(decoded.into_rgb8());
+    images.insert(path.to_path_buf(), img.clone());
+    Ok(img)
+}
+
+#[derive(Default)]
+struct MtlEntry {
+    diffuse: Option<Vec3>,
+    emission: Vec3,
+    specular: Vec3,
+    shininess: Option<f32>,
+    ior: Option<f32>,
+    dissolve: f32,
+    illum: u32,
+    texture: Option<Picture>,
+}
+
+impl MtlEntry {
+    fn to_material(&self) -> Material {
+        let glass = matches!(self.illum, 4 | 6 | 7 | 9) || self.dissolve < 1.;
+        let metal = self.illum == 3 || (self.illum == 2 && self.specular.length_squared() > 0.75 && self.diffuse.as_ref().is_none_or(|d| d.length_squared() < 0.01));
+        let refl = if glass {
+            Reflection::Glass { reflective: self.ior.unwrap_or(1.5) }
+        } else if metal {
+            //blinn-phong exponent to roughness, http://simonstechblog.blogspot.com/2011/12/microfacet-brdf.html
+            let roughness = self.shininess.map_or(0., |ns| (2. / (ns + 2.)).sqrt()).clamp(0., 1.);
+            Reflection::Metal { roughness }
+        } else {
+            Reflection::Diffuse()
+        };
+        let tex = match &self.texture {
+            Some(img) => Texture::Img { img: img.clone() },
+            None if metal => Texture::Solid { color: self.specular.clone() },
+            None => Texture::Solid { color: self.diffuse.clone().unwrap_or(Vec3::new1(1.)) },
+        };
+        Material::new(refl, tex, self.emission.clone())
+    }
+}
+
+fn load_mtl(path: &Path, images: &mut HashMap<PathBuf, Picture>) -> Result<HashMap<String, Material>, MeshError> {
+    let src = read(path)?;
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+    let mut sol: HashMap<String, Material> = HashMap::new();
+    let mut current: Option<(String, MtlEntry)> = None;
+    for (i, text) in src.lines().enumerate() {
+        let line = i + 1;
+        let text = text.split('#').next().unwrap_or("");
+        let tokens: Vec<&str> = text.split_whitespace().collect();
+        let Some((&keyword, args)) = tokens.split_first() else {
+            continue;
+        };
+        if keyword == "newmtl" {
+            if let Some((name, entry)) = current.take() {
+                sol.insert(name, entry.to_material());
+            }
+            current = Some((rest_of_line(text, keyword).to_string(), MtlEntry { dissolve: 1., illum: 2, ..Default::default() }));
+            continue;
+        }
+        let Some((_, entry)) = current.as_mut() else {
+            return Err(parse_err(path, line, format!("'{}' before any newmtl", keyword)));
+        };
+        match keyword {
+            "Kd" => entry.diffuse = Some(Vec3::from(floats::<3>(path, line, args)?)),
+            "Ke" => entry.emission = Vec3::from(floats::<3>(path, line, args)?),
+            "Ks" => entry.specular = Vec3::from(floats::<3>(path, line, args)?),
+            "Ns" => entry.shininess = Some(floats::<1>(path, line, args)?[0]),
+            "Ni" => entry.ior = Some(floats::<1>(path, line, args)?[0]),
+            "d" => entry.dissolve = floats::<1>(path, line, args)?[0],
+            "Tr" => entry.dissolve = 1. - floats::<1>(path, line, args)?[0],
+            "illum" => {
+                entry.illum = args.first().and_then(|t| t.parse().ok()).ok_or(parse_err(path, line, "illum expects a whole number".to_string()))?;
+            }
+            "map_Kd" => {
+                //options like -s or -o come before the file name, only the name is used
+                let name = args.last().ok_or(parse_err(path, line, "map_Kd expects a file name".to_string()))?;
+                let img = load_image(&base_dir.join(name), images).map_err(|msg| parse_err(path, line, msg))?;
+                entry.texture = Some(img);
+            }
+            //ambient color, transmission filter, bump maps and the rest have no counterpart in Material
+            _ => {}
+        }
+    }
+    if let Some((name, entry)) = current {
+        sol.insert(name, entry.to_material());
+    }
+    Ok(sol)
+}
+
+//loads a wavefront obj file together with the mtl libraries it references
+pub fn load_obj(path: impl AsRef<Path>) -> Result<Mesh, MeshError> {
+    let path = path.as_ref();
+    let src = read(path)?;
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+    let mut mesh = Mesh::default();
+    let mut library: HashMap<String, Material> = HashMap::new();
+    let mut images: HashMap<PathBuf, Picture> = HashMap::new();
+    let mut mat_index: HashMap<String, usize> = HashMap::new();
+    let mut current_mat = 0;
+    mesh.materials.push(Material::default());
+
+    for (i, text) in src.lines().enumerate() {
+        let line = i + 1;
+        let text = text.split('#').next().unwrap_or("");
+        let tokens: Vec<&str> = text.split_whitespace().collect();
+        let Some((&keyword, args)) = tokens.split_first() else {
+            continue;
+        };
+        match keyword {
+            "v" => mesh.positions.push(Vec3::from(floats::<3>(path, line, args)?)),
+            "vn" => mesh.normals.push(Vec3::from(floats::<3>(path, line, args)?)),
+            "vt" => {
+                let [u] = floats::<1>(path, line, args)?;
+                let v = if args.len() > 1 { floats::<2>(path, line, args)?[1] } else { 0. };
+                mesh.uvs.push((u, v));
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return Err(parse_err(path, line, "a face needs at least 3 vertices".to_string()));
+                }
+                let mut corners: Vec<(usize, Option<usize>, Option<usize>)> = vec![];
+                for arg in args {
+                    let mut parts = arg.split('/');
+                    let v = resolve_index(path, line, parts.next().unwrap_or(""), mesh.positions.len())?;
+                    let vt = match parts.next() {
+                        Some("") | None => None,
+                        Some(t) => Some(resolve_index(path, line, t, mesh.uvs.len())?),
+                    };
+                    let vn = match parts.next() {
+                        Some("") | None => None,
+                        Some(n) => Some(resolve_index(path, line, n, mesh.normals.len())?),
+                    };
+                    corners.push((v, vt, vn));
+                }
+                //polygons are split into a triangle fan
+                for k in 1..corners.len() - 1 {
+                    let tri = [&corners[0], &corners[k], &corners[k + 1]];
+                    let uvs = match tri.map(|c| c.1) {
+                        [Some(a), Some(b), Some(c)] => Some([a, b, c]),
+                        _ => None,
+                    };
+                    let normals = match tri.map(|c| c.2) {
+                        [Some(a), Some(b), Some(c)] => Some([a, b, c]),
+                        _ => None,
+                    };
+                    mesh.triangles.push(MeshTriangle { vertices: tri.map(|c| c.0), normals, uvs, mat: current_mat });
+                }
+            }
+            "mtllib" => {
+                let lib_path = base_dir.join(rest_of_line(text, keyword));
+                library.extend(load_mtl(&lib_path, &mut images)?);
+            }
+            "usemtl" => {
+                let name = rest_of_line(text, keyword);
+                let mat = library.get(name).ok_or(parse_err(path, line, format!("unknown material '{}'", name)))?;
+                current_mat = *mat_index.entry(name.to_string()).or_insert_with(|| {
+                    mesh.materials.push(mat.clone());
+                    mesh.materials.len() - 1
+                });
+            }
+            //groups, objects, smoothing groups and free form geometry are not needed for rendering
+            _ => {}
+        }
+    }
+    if mesh.triangles.is_empty() {
+        return Err(parse_err(path, src.lines().count(), "the file contains no faces".to_string()));
+    }
+    Ok(mesh)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //writes the files into a directory of their own and loads the first one
+    fn load(files: &[(&str, &str)]) -> Result<Mesh, MeshError> {
+        let dir = std::env::temp_dir().join(format!("raytracing-mesh-{}-{}", std::process::id(), files[0].0));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, src) in files {
+            std::fs::write(dir.join(name), src).unwrap();
+        }
+        let mesh = load_obj(dir.join(files[0].0));
+        std::fs::remove_dir_all(&dir).unwrap();
+        mesh
+    }
+
+    #[test]
+    fn obj_faces_become_triangles_with_their_material() {
+        let obj = "mtllib square.mtl\nv 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\nvn 0 0 1\n\
+                   usemtl red\nf 1/1/1 2/2/1 3/3/1 4/4/1\nf -4 -2 -1\n";
+        let mtl = "newmtl red\nKd 1 0 0\nKe 0 0 2\n";
+        let mesh = load(&[("square.obj", obj), ("square.mtl", mtl)]).unwrap();
+        assert_eq!((mesh.positions.len(), mesh.uvs.len(), mesh.triangles.len()), (4, 4, 3));
+        //the quad is split into a fan around its first corner, negative indices count back from the last vertex
+        assert_eq!(mesh.triangles.iter().map(|t| t.vertices).collect::<Vec<_>>(), [[0, 1, 2], [0, 2, 3], [0, 2, 3]]);
+        assert!(mesh.triangles.iter().all(|t| t.mat == 1));
+        assert_eq!(mesh.materials[1].emmision, Vec3::new(0., 0., 2.));
+
+        let hit = mesh.intersect_triangle(0, &Ray::new(Vec3::new(0.75, 0.25, 1.), Vec3::new(0., 0., -1.))).unwrap();
+        assert_eq!((&hit.p, &hit.normal), (&Vec3::new(0.75, 0.25, 0.), &Vec3::new(0., 0., 1.)));
+        assert!((hit.u - 0.75).abs() < 1e-5 && (hit.v - 0.75).abs() < 1e-5, "uv ({}, {})", hit.u, hit.v);
+        assert_eq!(hit.albedo(), Vec3::new(1., 0., 0.));
+        assert!(mesh.intersect_triangle(0, &Ray::new(Vec3::new(0.25, 0.75, 1.), Vec3::new(0., 0., -1.))).is_none());
+    }
+
+    #[test]
+    fn obj_errors_name_their_line() {
+        let Err(MeshError::Parse { line, .. }) = load(&[("broken.obj", "v 0 0 0\nv 1 0 0\n\nf 1 2 3\n")]) else {
+            panic!("a face with a missing vertex loaded")
+        };
+        assert_eq!(line, 4);
+        assert!(matches!(load(&[("missing.obj", "mtllib nowhere.mtl\n")]), Err(MeshError::Io { .. })));
+    }
+}
@@ -1,19 +1,45 @@
-use crate::{Vec3, geometry::{Material, HitInfo, Ray}, textures::Texture, geometry::scatter};
+use crate::{Vec3, Uniforms, geometry::{Material, HitInfo, Ray}, textures::Texture, bsdf::Bsdf, spectrum::Wavelengths, bvh::{Bvh, Aabb}, mesh::Mesh, transform::Transform, lights::{Light, LightShape}, sampler::Sampler, medium::{Medium, Interface, HenyeyGreenstein}};
+use std::sync::Arc;
+
+//hits closer than this are the surface the ray started from
+pub(crate) const MIN_HIT_DIST: f32 = 0.0001;
+pub(crate) const MAX_HIT_DIST: f32 = 100000.;
+//flat quads still need a volume for the slab test
+const BOUNDS_PADDING: f32 = 0.0001;
+//most Volume boundaries a path or shadow ray passes before it is given up, also ends the inside test of Volume
+const MAX_CROSSINGS: u32 = 64;
 
 
 pub enum Object {
     Sphere {pos: Vec3, rad: f32, mat: Material},
     Plane {pos: Vec3, normal: Vec3, mat: Material},
     BoundBox {min: Vec3, max: Vec3, inside: Vec<Object>},
-    Quad {pos: Vec3, delta_x: Vec3, delta_y: Vec3, kind: QuadType, n: Vec3, w: Vec3, mat: Material}
+    Quad {pos: Vec3, delta_x: Vec3, delta_y: Vec3, kind: QuadType, n: Vec3, w: Vec3, mat: Material},
+    Bvh(Bvh),
+    //one triangle of a shared mesh, see Mesh::into_object
+    Triangle {mesh: Arc<Mesh>, index: usize},
+    //the inner object can be shared between many transforms, see new_instance
+    Transform {transform: Transform, inner: Arc<Object>},
+    //fog or smoke filling a closed shape, the surface of boundary is only where rays enter and leave it
+    Volume {boundary: Box<Object>, medium: Medium}
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum QuadType{
     Rect(),
     Triangle(),
     Disk(),
 }
 
+//a point where a path scatters, on a surface or inside a medium
+struct Vertex<'a> {
+    p: &'a Vec3,
+    incoming: &'a Vec3,
+    //None inside a medium
+    normal: Option<&'a Vec3>,
+    medium: Option<&'a Medium>,
+}
+
 impl QuadType{
     fn get_fn(&self)->impl Fn(f32, f32)->bool{
         match self {
@@ -34,36 +60,32 @@ impl Object {
 
     fn calc_quadrilet(p: &Vec3, u: &Vec3, v: &Vec3, w: &Vec3) -> (f32, f32){
         let alpha = w.dot(&p.cross(v));
-        let beta = w.dot(&u.cross(&p));
+        let beta = w.dot(&u.cross(p));
         (alpha, beta)
     }
 
-    fn intersect(&self, ray: &Ray) -> Option<HitInfo>{
+    pub(crate) fn intersect(&self, ray: &Ray) -> Option<HitInfo>{
         assert!(ray.dir.is_normalized());
         match self {
             //https://kylehalladay.com/blog/tutorial/math/2013/12/24/Ray-Sphere-Intersection.html
             Self::Sphere {pos, rad, mat} => {
                 let camera_self = pos - &ray.start;
                 let project_len = camera_self.dot(&ray.dir);
-                if project_len < 0.0{
-                    None
-                } else {
-                    let closest = camera_self.length_squared()-project_len*project_len;
-                    let rad2 = rad * rad;
-                    if closest > rad2{
-                        None
-                    } else {
-                        let t1c = (rad2 - closest).sqrt();
-                        let inters = project_len - t1c;
-                        if inters <= 0. {
-                            return None;
-                        }
-                        let normal = (&ray.start + &ray.dir * inters - pos).normalize();
-                        let hitp = &ray.start + &ray.dir * inters;
-                        let (u,v) = Texture::sphere_uv_coord(pos, &hitp);
-                        Some(HitInfo{p: hitp, normal, material: mat.clone(), u, v})
-                    }
+                let closest = camera_self.length_squared()-project_len*project_len;
+                let rad2 = rad * rad;
+                if closest > rad2{
+                    return None;
+                }
+                let t1c = (rad2 - closest).sqrt();
+                //rays that start inside the sphere, or on it, leave through the far side
+                let inters = if project_len - t1c > MIN_HIT_DIST { project_len - t1c } else { project_len + t1c };
+                if inters <= MIN_HIT_DIST {
+                    return None;
                 }
+                let hitp = &ray.start + &ray.dir * inters;
+                let normal = (&hitp - pos).normalize();
+                let (u,v) = Texture::sphere_uv_coord(pos, &hitp);
+                Some(HitInfo{p: hitp, normal, material: mat.clone(), u, v, light: None, interface: None})
             }
             Self::Plane {pos, normal, mat} => {
                 //https://www.scratchapixel.com/lessons/3d-basic-rendering/minimal-ray-tracer-rendering-simple-shapes/ray-plane-and-ray-disk-intersection.html
@@ -75,7 +97,7 @@ impl Object {
                 let denom = n.dot(&ray.dir);
                 let t = (pos - &ray.start).dot(&n) / denom;
                 if t > 0.{
-                    let hit = HitInfo{p: &ray.start + &ray.dir * t, normal: n.clone(), material: mat.clone(), u: 0., v: 0.};
+                    let hit = HitInfo{p: &ray.start + &ray.dir * t, normal: n.clone(), material: mat.clone(), u: 0., v: 0., light: None, interface: None};
                     return Some(hit);
                 }
                 None  
@@ -109,42 +131,260 @@ impl Object {
                 }
                 None
             }
+            Self::Bvh(bvh) => bvh.hit(ray),
+            Self::Triangle { mesh, index } => mesh.intersect_triangle(*index, ray),
+            Self::Transform { transform, inner } => {
+                inner.intersect(&transform.inverse_ray(ray)).map(|hit| transform.apply_hit(hit))
+            }
+            Self::Volume { boundary, medium } => {
+                let mut hit = boundary.intersect(ray)?;
+                //the ray is inside when it crosses the closed boundary an odd number of times from here on
+                let mut crossings = 0;
+                let mut ahead = Ray::new(hit.p.clone(), ray.dir.clone());
+                for _ in 0..MAX_CROSSINGS {
+                    let Some(next) = boundary.intersect(&ahead) else {
+                        break;
+                    };
+                    //a hit at the start is the crossing the ray is already on
+                    let dist = (&next.p - &ahead.start).length();
+                    if dist >= MIN_HIT_DIST {
+                        crossings += 1;
+                    }
+                    ahead = Ray::new(&ahead.start + &ahead.dir * dist.max(MIN_HIT_DIST), ahead.dir.clone());
+                }
+                hit.light = None;
+                hit.interface = Some(Interface { medium: medium.clone(), leaving: crossings % 2 == 0 });
+                Some(hit)
+            }
         }
     }
-    pub fn bounce(ray: &Ray, objs: &Vec<Object>, max_bounce: u8, env_shader: &Box<dyn Fn(&Vec3)->Vec3+Send+Sync>) -> Vec3{
-        assert!(ray.dir.is_normalized());
-        if max_bounce <= 0 {
-            return Vec3::new(0., 0., 0.);
+    //power heuristic weight of a sample taken with pdf a when pdf b could have taken it too
+    fn mis_weight(a: f32, b: f32) -> f32 {
+        if a <= 0. {
+            return 0.;
         }
+        a * a / (a * a + b * b)
+    }
 
-        match Self::hit_all(ray, objs) {
-            Some(hit) => {
-                let r = scatter(ray, &hit);
-                let future = Self::bounce(&r, objs, max_bounce - 1, env_shader);
-                hit.get_color(future)
+    //radiance arriving along the ray, the path is followed until it leaves the scene, reaches bounce_count
+    //or is ended by russian roulette after min_bounce_count
+    pub fn bounce(ray: &Ray, input: &Uniforms, sampler: &mut dyn Sampler) -> Vec3{
+        let mut ray = ray.clone();
+        let mut radiance = Vec3::default();
+        //how much of the light found at the current hit reaches the camera
+        let mut throughput = Vec3::new1(1.);
+        //the pdf the ray was scattered with, None for camera rays and specular reflections
+        let mut scatter_pdf: Option<f32> = None;
+        //where the ray was last scattered, passing the boundary of a Volume does not move it
+        let mut origin = ray.start.clone();
+        //the medium the ray travels through, None in empty space
+        let mut medium = input.camera_medium.clone();
+        //in the spectral mode the path carries three wavelengths, every color is turned into its values at them
+        let mut wavelengths = input.spectral.then(|| Wavelengths::sample(sampler.get_1d()));
+        let color = |c: &Vec3, wavelengths: Option<&Wavelengths>| match wavelengths {
+            Some(w) => w.upsample(c),
+            None => c.clone(),
+        };
+        let mut depth = 0;
+        let mut crossings = 0;
+        while depth < input.bounce_count {
+            assert!(ray.dir.is_normalized());
+            let hit = Self::hit_all(&ray, &input.objects);
+            //inside a medium the ray may scatter before it gets to the hit
+            if let Some(m) = &medium {
+                let m = m.at(wavelengths.as_ref());
+                let dist = match &hit {
+                    Some(hit) => (&hit.p - &ray.start).length(),
+                    None => input.atmosphere.as_ref().map_or(f32::INFINITY, |a| a.depth),
+                };
+                let (scatter, weight) = m.sample_distance(dist, sampler.get_2d());
+                throughput = throughput * weight;
+                if let Some(t) = scatter {
+                    let p = &ray.start + &ray.dir * t;
+                    let phase = HenyeyGreenstein { g: m.g };
+                    let vertex = Vertex { p: &p, incoming: &ray.dir, normal: None, medium: medium.as_ref() };
+                    radiance = radiance + &throughput * Self::sample_light(&vertex, &phase, wavelengths.as_ref(), input, sampler);
+                    let Some(sample) = phase.sample(&ray.dir, &ray.dir, sampler) else {
+                        break;
+                    };
+                    scatter_pdf = Some(sample.pdf);
+                    throughput = throughput * sample.weight;
+                    if !Self::survive(&mut throughput, depth, input, sampler) {
+                        break;
+                    }
+                    depth += 1;
+                    ray = Ray::new(p.clone(), sample.dir);
+                    origin = p;
+                    continue;
+                }
+            }
+            let Some(hit) = hit else {
+                radiance = radiance + &throughput * color(&(input.env_shader)(&ray.dir), wavelengths.as_ref());
+                break;
+            };
+            if let Some(interface) = &hit.interface {
+                crossings += 1;
+                if crossings > MAX_CROSSINGS {
+                    break;
+                }
+                medium = if interface.leaving { Self::outside(input) } else { Some(interface.medium.clone()) };
+                ray = Ray::new(hit.p.clone(), ray.dir.clone());
+                continue;
+            }
+            //lights were also sampled directly at the previous hit, weigh both ways of reaching them
+            let emission = match (&hit.light, scatter_pdf) {
+                (Some(light), Some(pdf)) if !input.lights.is_empty() => {
+                    let light_pdf = input.lights.selection_pdf(light) * light.pdf(&origin, &hit.p);
+                    color(&hit.material.emmision, wavelengths.as_ref()) * Self::mis_weight(pdf, light_pdf)
+                }
+                _ => color(&hit.material.emmision, wavelengths.as_ref()),
+            };
+            radiance = radiance + &throughput * emission;
 
+            let bsdf = hit.bsdf(wavelengths.as_mut());
+            if !bsdf.is_specular() {
+                let vertex = Vertex { p: &hit.p, incoming: &ray.dir, normal: Some(&hit.normal), medium: medium.as_ref() };
+                radiance = radiance + &throughput * Self::sample_light(&vertex, bsdf.as_ref(), wavelengths.as_ref(), input, sampler);
             }
+            let Some(sample) = bsdf.sample(&ray.dir, &hit.normal, sampler) else {
+                break;
+            };
+            scatter_pdf = if sample.specular { None } else { Some(sample.pdf) };
+            throughput = throughput * sample.weight;
+            if !Self::survive(&mut throughput, depth, input, sampler) {
+                break;
+            }
+            depth += 1;
+            origin = hit.p.clone();
+            ray = Ray::new(hit.p, sample.dir);
+        }
+        match &wavelengths {
+            Some(w) => w.to_rgb(&radiance),
+            None => radiance,
+        }
+    }
+
+    //dim paths are ended at random after min_bounce_count, the survivors are brightened by the same chance
+    fn survive(throughput: &mut Vec3, depth: u8, input: &Uniforms, sampler: &mut dyn Sampler) -> bool {
+        if depth + 1 < input.min_bounce_count {
+            return true;
+        }
+        let survive = throughput.x.max(throughput.y).max(throughput.z).min(0.95);
+        if survive <= 0. || sampler.get_1d() >= survive {
+            return false;
+        }
+        *throughput = &*throughput / survive;
+        true
+    }
+
+    //the medium outside of every Volume
+    fn outside(input: &Uniforms) -> Option<Medium> {
+        input.atmosphere.as_ref().map(|a| a.medium.clone())
+    }
 
-            None => {
-                
-                env_shader(&ray.dir)
+    //the medium around the camera, the one of the Volume it is in or else the atmosphere
+    pub fn medium_at_camera(input: &Uniforms) -> Option<Medium> {
+        //any direction works for a closed boundary, the first one found ahead tells whether the camera is inside
+        let mut ray = Ray::new(input.cam.origin().clone(), Vec3::up());
+        for _ in 0..MAX_CROSSINGS {
+            let Some(hit) = Self::hit_all(&ray, &input.objects) else {
+                break;
+            };
+            if let Some(interface) = hit.interface {
+                return if interface.leaving { Some(interface.medium) } else { Self::outside(input) };
             }
-        } 
+            ray = Ray::new(hit.p, ray.dir);
+        }
+        Self::outside(input)
+    }
+
+    //next event estimation: light scattered at the vertex that arrives straight from one randomly picked light
+    fn sample_light(vertex: &Vertex, bsdf: &dyn Bsdf, wavelengths: Option<&Wavelengths>, input: &Uniforms, sampler: &mut dyn Sampler) -> Vec3{
+        let pick = sampler.get_1d();
+        let (u1, u2) = sampler.get_2d();
+        let Some((light, pick_pdf)) = input.lights.pick(pick) else {
+            return Vec3::default();
+        };
+        let Some(sample) = light.sample(vertex.p, u1, u2) else {
+            return Vec3::default();
+        };
+        let light_pdf = sample.pdf * pick_pdf;
+        //a phase function ignores the normal
+        let normal = vertex.normal.unwrap_or(vertex.incoming);
+        let scatter_pdf = bsdf.pdf(vertex.incoming, normal, &sample.dir);
+        if vertex.normal.is_some_and(|n| n.dot(&sample.dir) <= 0.) || light_pdf <= 0. || scatter_pdf <= 0. {
+            return Vec3::default();
+        }
+        let shadow = Ray::new(vertex.p.clone(), sample.dir.clone());
+        let transmittance = Self::transmittance(&shadow, sample.dist, vertex.medium, wavelengths, input);
+        let radiance = match wavelengths {
+            Some(w) => w.upsample(&sample.radiance),
+            None => sample.radiance,
+        };
+        radiance * transmittance * bsdf.eval(vertex.incoming, normal, &sample.dir) * (Self::mis_weight(light_pdf, scatter_pdf) / light_pdf)
+    }
+
+    //the fraction of light that gets dist along the ray, 0 if a surface is in the way,
+    //medium is where the ray starts and changes at the boundaries of volumes
+    fn transmittance(ray: &Ray, dist: f32, medium: Option<&Medium>, wavelengths: Option<&Wavelengths>, input: &Uniforms) -> Vec3 {
+        let mut ray = ray.clone();
+        let mut left = dist;
+        let mut medium = medium.cloned();
+        let mut transmittance = Vec3::new1(1.);
+        let mut crossings = 0;
+        while let Some(blocker) = Self::hit_all(&ray, &input.objects) {
+            let d = (&blocker.p - &ray.start).length();
+            if d >= left * 0.999 {
+                break;
+            }
+            let Some(interface) = blocker.interface else {
+                return Vec3::default();
+            };
+            crossings += 1;
+            if crossings > MAX_CROSSINGS {
+                return Vec3::default();
+            }
+            if let Some(m) = &medium {
+                transmittance = transmittance * m.at(wavelengths).transmittance(d);
+            }
+            medium = if interface.leaving { Self::outside(input) } else { Some(interface.medium) };
+            left -= d;
+            ray = Ray::new(blocker.p, ray.dir);
+        }
+        if let Some(m) = &medium {
+            transmittance = transmittance * m.at(wavelengths).transmittance(left);
+        }
+        transmittance
+    }
+
+    //the light list entry of an emissive Sphere or Quad
+    pub fn light(&self) -> Option<Light>{
+        let (shape, mat) = match self {
+            Self::Sphere { pos, rad, mat } => (LightShape::Sphere { pos: pos.clone(), rad: *rad }, mat),
+            Self::Quad { pos, delta_x, delta_y, kind, n, mat, .. } => (LightShape::Quad {
+                pos: pos.clone(), delta_x: delta_x.clone(), delta_y: delta_y.clone(), kind: *kind, normal: n.normalize()
+            }, mat),
+            _ => return None,
+        };
+        if mat.emmision == Vec3::default() {
+            return None;
+        }
+        Some(Light { shape, emission: mat.emmision.clone() })
     }
    
     
     pub fn hit_all(ray: &Ray, lis: &Vec<Self>) -> Option<HitInfo>{
-        let mut inf: Option<HitInfo> = None;
-        let mut min_dist = 100000.;
+        let mut inf: Option<(HitInfo, &Object)> = None;
+        let mut min_dist = MAX_HIT_DIST;
         for obj in lis {
-            match Self::intersect(&obj, ray){
+            match Self::intersect(obj, ray){
                 Some(i) => {
                     let len = (&i.p - &ray.start).length();
-                    if len < 0.0001{
+                    if len < MIN_HIT_DIST{
                         continue;
                     }
                     else if len < min_dist {
-                        inf = Some(i);
+                        inf = Some((i, obj));
                         min_dist = len;
                     } else {
                         continue;
@@ -155,14 +395,97 @@ impl Object {
             }
         }
 
-        inf
+        inf.map(|(hit, obj)| obj.with_light(hit))
+    }
+
+    //fills in the light of the closest hit, intersect leaves it out because most hits are discarded
+    //hits from inside groups already got theirs, objects without an entry in the light list return None
+    pub(crate) fn with_light(&self, mut hit: HitInfo) -> HitInfo{
+        if hit.light.is_none() && hit.material.emmision != Vec3::default() {
+            hit.light = self.light();
+        }
+        hit
     }
 
     pub fn new_quad(pos: Vec3, delta_x: Vec3, delta_y: Vec3, kind: QuadType, mat: Material) -> Self{
         let n = delta_y.cross(&delta_x);
-        let w = &n / &n.dot(&n);
+        let w = &n / n.dot(&n);
         Self::Quad { pos, delta_x, delta_y, kind, n, w, mat }
     }
+
+    //axis aligned box around the object, None if it reaches infinity
+    pub fn bounds(&self) -> Option<Aabb>{
+        match self {
+            Self::Sphere { pos, rad, .. } => Some(Aabb::new(pos - Vec3::new1(*rad), pos + Vec3::new1(*rad))),
+            Self::Plane { .. } => None,
+            Self::Quad { pos, delta_x, delta_y, kind, .. } => {
+                let bounds = match kind {
+                    QuadType::Triangle() => Aabb::from_points(&[pos.clone(), pos + delta_x, pos + delta_y]),
+                    QuadType::Rect() => Aabb::from_points(&[pos.clone(), pos + delta_x, pos + delta_y, pos + delta_x + delta_y]),
+                    QuadType::Disk() => {
+                        //pos + cos(t)*delta_x + sin(t)*delta_y reaches sqrt(dx^2+dy^2) on every axis
+                        let extent = Vec3::new(
+                            (delta_x.x * delta_x.x + delta_y.x * delta_y.x).sqrt(),
+                            (delta_x.y * delta_x.y + delta_y.y * delta_y.y).sqrt(),
+                            (delta_x.z * delta_x.z + delta_y.z * delta_y.z).sqrt(),
+                        );
+                        Aabb::new(pos - &extent, pos + &extent)
+                    }
+                };
+                Some(bounds.pad(BOUNDS_PADDING))
+            }
+            Self::BoundBox { inside, .. } => Self::bounds_of(inside),
+            Self::Bvh(bvh) => bvh.bounds(),
+            Self::Triangle { mesh, index } => Some(mesh.triangle_bounds(*index).pad(BOUNDS_PADDING)),
+            Self::Transform { transform, inner } => inner.bounds().map(|b| transform.apply_bounds(&b)),
+            Self::Volume { boundary, .. } => boundary.bounds(),
+        }
+    }
+
+    //the box around every object in the list, None if any of them is unbounded or the list is empty
+    pub fn bounds_of(objs: &[Object]) -> Option<Aabb>{
+        let mut sol: Option<Aabb> = None;
+        for obj in objs {
+            let b = obj.bounds()?;
+            sol = Some(match sol {
+                Some(acc) => acc.union(&b),
+                None => b,
+            });
+        }
+        sol
+    }
+
+    //wraps the objects in a BoundBox that fits them exactly
+    pub fn new_bound_box(inside: Vec<Object>) -> Self{
+        assert!(!inside.is_empty(), "new_bound_box needs at least one object");
+        let Aabb { min, max } = Self::bounds_of(&inside).expect("new_bound_box can not contain unbounded objects like Plane");
+        Self::BoundBox { min, max, inside }
+    }
+
+    pub fn new_transform(obj: Object, transform: Transform) -> Self{
+        Self::Transform { transform, inner: Arc::new(obj) }
+    }
+
+    //places a shared object again without copying its geometry
+    pub fn new_instance(obj: &Arc<Object>, transform: Transform) -> Self{
+        Self::Transform { transform, inner: obj.clone() }
+    }
+
+    //fills the closed shape the objects make up with the medium, their materials are not used
+    pub fn new_volume(boundary: Vec<Object>, medium: Medium) -> Self{
+        assert!(!boundary.is_empty(), "new_volume needs at least one object");
+        assert!(Self::bounds_of(&boundary).is_some(), "new_volume can not contain unbounded objects like Plane");
+        let boundary = match <[Object; 1]>::try_from(boundary) {
+            Ok([obj]) => obj,
+            Err(objs) => Self::new_bvh(objs),
+        };
+        Self::Volume { boundary: Box::new(boundary), medium }
+    }
+
+    //puts the objects into a bounding volume hierarchy, BoundBoxes inside are dissolved
+    pub fn new_bvh(objs: Vec<Object>) -> Self{
+        Self::Bvh(Bvh::new(objs))
+    }
 }
 
 
@@ -232,4 +555,61 @@ pub mod abstract_object {
         let radius = (2.*f32::powi(size, 2)).sqrt()/2.;
         self::new_cylinder(pos, delta_y, 4, radius, true, mat)
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{geometry::Reflection, sampler::SamplerKind, spectrum::Ior};
+
+    //the average color of paths shot from the camera at the middle of the scene, under a white environment
+    fn furnace(objects: Vec<Object>, spectral: bool, samples: u32) -> Vec3 {
+        let mut uni = Uniforms { spectral, bounce_count: 64, objects, env_shader: Box::new(|_: &Vec3| Vec3::new1(1.)), ..Default::default() };
+        uni.prepare();
+        let mut sampler = SamplerKind::Independent.create(samples, 1);
+        let mut sum = Vec3::default();
+        for i in 0..samples {
+            sampler.start_sample(0, 0, i);
+            let ray = Ray::new(Vec3::new(0., -5., 0.), Vec3::new(0., 1., 0.));
+            sum = sum + Object::bounce(&ray, &uni, sampler.as_mut());
+        }
+        sum / samples as f32
+    }
+
+    #[test]
+    fn dispersive_glass_conserves_energy() {
+        let glass = Material::new(Reflection::Dispersive { ior: Ior::preset("sf11").unwrap() }, Texture::Solid { color: Vec3::new1(1.) }, Vec3::default());
+        for spectral in [false, true] {
+            //a glass block the rays enter and leave, every path through it crosses two dispersive surfaces
+            let slab = abstract_object::new_box(&Vec3::default(), &Vec3::new(0., 0., 2.), 1., glass.clone());
+            let color = furnace(slab, spectral, 20000);
+            for c in [color.x, color.y, color.z] {
+                assert!((c - 1.).abs() < 0.1, "the slab turned white into {:?} with spectral {}", color, spectral);
+            }
+        }
+    }
+
+    #[test]
+    fn light_sampling_and_scattering_are_weighed_to_one() {
+        //a grey floor lit by a sphere it sees under a half angle of 30 degrees, under a black sky,
+        //so the floor reflects albedo * emission * sin2 of that angle
+        let floor = Material::new(Reflection::Diffuse(), Texture::Solid { color: Vec3::new1(0.5) }, Vec3::default());
+        let lamp = Material::new(Reflection::Diffuse(), Texture::Solid { color: Vec3::default() }, Vec3::new1(4.));
+        let objects = vec![
+            Object::Plane { pos: Vec3::default(), normal: Vec3::new(0., -1., 0.), mat: floor },
+            Object::Sphere { pos: Vec3::new(0., -2., 0.), rad: 1., mat: lamp },
+        ];
+        //two bounces let the scattered ray find the lamp too
+        let mut uni = Uniforms { bounce_count: 2, objects, env_shader: Box::new(|_: &Vec3| Vec3::default()), ..Default::default() };
+        uni.prepare();
+        let samples = 20000;
+        let mut sampler = SamplerKind::Independent.create(samples, 1);
+        let mut sum = Vec3::default();
+        for i in 0..samples {
+            sampler.start_sample(0, 0, i);
+            let ray = Ray::new(Vec3::new(3., -1., 0.), Vec3::new(-3., 1., 0.).normalize());
+            sum = sum + Object::bounce(&ray, &uni, sampler.as_mut());
+        }
+        let color = sum / samples as f32;
+        assert!((color.x - 0.5).abs() < 0.02, "the floor reflects {:?} instead of 0.5", color);
+    }
+}
@@ -0,0 +1,161 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use exr::prelude::{AnyChannel, AnyChannels, Encoding, FlatSamples, Image, Layer, LayerAttributes, SmallVec, WritableImage};
+use image::{Rgb, codecs::hdr::HdrEncoder};
+use crate::render::FloatPicture;
+
+//an extra image written next to the color, e.g. normals or the sample count of every pixel
+pub struct RenderPass {
+    pub name: String,
+    pub image: FloatPicture,
+    //only the red channel holds data
+    pub mono: bool,
+}
+
+impl RenderPass {
+    pub fn rgb(name: &str, image: FloatPicture) -> Self {
+        Self { name: name.to_string(), image, mono: false }
+    }
+
+    pub fn mono(name: &str, image: FloatPicture) -> Self {
+        Self { name: name.to_string(), image, mono: true }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HdrFormat {
+    //https://openexr.com, the only format that keeps render passes
+    Exr,
+    //https://en.wikipedia.org/wiki/RGBE_image_format
+    Radiance,
+    //https://www.pauldebevec.com/Research/HDR/PFM/
+    Pfm,
+}
+
+impl HdrFormat {
+    //None for the 8 bit formats
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "exr" => Some(Self::Exr),
+            "hdr" => Some(Self::Radiance),
+            "pfm" => Some(Self::Pfm),
+            _ => None,
+        }
+    }
+}
+
+//one channel of every pixel, top row first
+fn channel(pic: &FloatPicture, offset: usize) -> Vec<f32> {
+    pic.data().iter().skip(offset).step_by(3).copied().collect()
+}
+
+fn write_exr(path: &Path, pic: &FloatPicture, passes: &[RenderPass]) -> io::Result<()> {
+    let mut channels: Vec<AnyChannel<FlatSamples>> = vec![];
+    for (i, name) in ["R", "G", "B"].iter().enumerate() {
+        channels.push(AnyChannel::new(*name, FlatSamples::F32(channel(pic, i))));
+    }
+    for pass in passes {
+        assert!(pass.image.width == pic.width && pass.image.height == pic.height, "render pass {} has a different size than the image", pass.name);
+        if pass.mono {
+            channels.push(AnyChannel::new(format!("{}.Y", pass.name).as_str(), FlatSamples::F32(channel(&pass.image, 0))));
+        } else {
+            for (i, name) in ["R", "G", "B"].iter().enumerate() {
+                channels.push(AnyChannel::new(format!("{}.{}", pass.name, name).as_str(), FlatSamples::F32(channel(&pass.image, i))));
+            }
+        }
+    }
+    let layer = Layer::new(
+        (pic.width as usize, pic.height as usize),
+        LayerAttributes::named("render"),
+        Encoding::FAST_LOSSLESS,
+        AnyChannels::sort(SmallVec::from_vec(channels)),
+    );
+    Image::from_layer(layer).write().to_file(path).map_err(io::Error::other)
+}
+
+fn write_radiance(path: &Path, pic: &FloatPicture) -> io::Result<()> {
+    let pixels: Vec<Rgb<f32>> = pic.data().chunks_exact(3).map(|c| Rgb([c[0].max(0.), c[1].max(0.), c[2].max(0.)])).collect();
+    let file = BufWriter::new(File::create(path)?);
+    HdrEncoder::new(file).encode(&pixels, pic.width as usize, pic.height as usize).map_err(io::Error::other)
+}
+
+fn write_pfm(path: &Path, pic: &FloatPicture) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    //the negative scale marks little endian data
+    write!(file, "PF\n{} {}\n-1.0\n", pic.width, pic.height)?;
+    //rows go from the bottom to the top
+    for row in pic.data().chunks_exact(pic.width as usize * 3).rev() {
+        for v in row {
+            file.write_all(&v.to_le_bytes())?;
+        }
+    }
+    file.flush()
+}
+
+//writes the linear radiance without clipping, passes are only kept by exr
+pub fn save_hdr(path: &Path, format: HdrFormat, pic: &FloatPicture, passes: &[RenderPass]) -> io::Result<()> {
+    match format {
+        HdrFormat::Exr => write_exr(path, pic, passes),
+        HdrFormat::Radiance => write_radiance(path, pic),
+        HdrFormat::Pfm => write_pfm(path, pic),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Vec3;
+
+    //2x2 with values far outside of 0-1, pixel (x, y) has the red value x + 2y
+    fn picture() -> FloatPicture {
+        let mut pic = FloatPicture::empty(2, 2);
+        for (x, y) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            pic.set_pixel(x, y, &Vec3::new((x + 2 * y) as f32, 0.25, 1000.));
+        }
+        pic
+    }
+
+    fn path(ext: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("raytracing-output-{}.{}", std::process::id(), ext))
+    }
+
+    #[test]
+    fn pfm_keeps_the_values_bottom_row_first() {
+        let path = path("pfm");
+        save_hdr(&path, HdrFormat::Pfm, &picture(), &[]).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let header = b"PF\n2 2\n-1.0\n";
+        assert_eq!(&bytes[..header.len()], header);
+        let values: Vec<f32> = bytes[header.len()..].chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect();
+        assert_eq!(values.len(), 12);
+        assert_eq!([values[0], values[3], values[6], values[9]], [2., 3., 0., 1.]);
+        assert_eq!((values[1], values[2]), (0.25, 1000.));
+    }
+
+    #[test]
+    fn exr_and_radiance_keep_the_dynamic_range() {
+        let path_exr = path("exr");
+        let mut samples = FloatPicture::empty(2, 2);
+        samples.set_pixel(1, 1, &Vec3::new1(64.));
+        save_hdr(&path_exr, HdrFormat::Exr, &picture(), &[RenderPass::mono("samples", samples)]).unwrap();
+        let image = exr::prelude::read_all_flat_layers_from_file(&path_exr);
+        std::fs::remove_file(&path_exr).unwrap();
+        let channels = &image.unwrap().layer_data[0].channel_data.list;
+        let read = |name: &str| match &channels.iter().find(|c| c.name.to_string() == name).unwrap().sample_data {
+            FlatSamples::F32(v) => v.clone(),
+            _ => panic!("{} is not f32", name),
+        };
+        assert_eq!((read("R"), read("B")[0], read("samples.Y")), (vec![0., 1., 2., 3.], 1000., vec![0., 0., 0., 64.]));
+
+        let path_hdr = path("hdr");
+        save_hdr(&path_hdr, HdrFormat::Radiance, &picture(), &[]).unwrap();
+        let image = image::open(&path_hdr).map(|img| img.to_rgb32f());
+        std::fs::remove_file(&path_hdr).unwrap();
+        let pixel = image.unwrap().get_pixel(1, 1).0;
+        //rgbe shares one exponent between the channels, the small ones lose precision next to a large one
+        assert!((pixel[0] - 3.).abs() < 4. && (pixel[2] - 1000.).abs() < 10., "{:?}", pixel);
+    }
+}
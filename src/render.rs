@@ -1,36 +1,195 @@
-use crate::{Uniforms, WIDTH, HEIGHT, Vec3};
+use crate::{Uniforms, Vec3, color::ColorSpace, lights::luminance, tiles::{self, Tile}};
 use rayon::prelude::*;
 use indicatif::ProgressBar;
 use image::RgbImage;
 use std::sync::Arc;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
 use std::ops::{Deref, Index};
 
-fn parallel_row<F>(func: F, input: &Uniforms, y: usize) -> [Pixel; WIDTH]
-where
-    F: Fn(usize, usize, &Uniforms) -> Pixel + Sync + Send,
-{
-    let mut arr: [Pixel; WIDTH] = [Pixel::default(); WIDTH];
-    arr.par_iter_mut().enumerate().for_each(|(x, pixel)|
-        *pixel = func(x, y, input)
-    );
-    arr
+//the stats of every pixel of one tile, row by row
+struct TileStats {
+    tile: Tile,
+    stats: Vec<PixelStats>,
+}
+
+impl TileStats {
+    fn render<F>(&mut self, func: &F, input: &Uniforms, until: u32)
+    where F: Fn(usize, usize, &Uniforms, &mut PixelStats, u32) {
+        for ((x, y), stats) in self.tile.pixels().zip(self.stats.iter_mut()) {
+            func(x, y, input, stats, until);
+        }
+    }
+}
+
+//the samples of every pixel rendered so far, row by row from the top
+#[derive(Clone)]
+pub struct Frame {
+    pub width: usize,
+    pub height: usize,
+    pub stats: Vec<PixelStats>,
+}
+
+impl Frame {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self { width, height, stats: vec![PixelStats::default(); width * height] }
+    }
+
+    fn tile_stats(&self, tile: Tile) -> TileStats {
+        TileStats { stats: tile.pixels().map(|(x, y)| self.stats[y * self.width + x].clone()).collect(), tile }
+    }
+
+    fn write_tile(&mut self, tile: &TileStats) {
+        self.set_tile(&tile.tile, &tile.stats);
+    }
+
+    //stats holds the pixels of the tile row by row
+    pub(crate) fn set_tile(&mut self, tile: &Tile, stats: &[PixelStats]) {
+        for ((x, y), pixel) in tile.pixels().zip(stats.iter()) {
+            self.stats[y * self.width + x] = pixel.clone();
+        }
+    }
+
+    //the mean of the samples
+    pub fn color(&self) -> FloatPicture {
+        self.picture(|pixel| pixel.mean())
+    }
+
+    //how many samples every pixel took, the count is in all three channels
+    pub fn samples(&self) -> FloatPicture {
+        self.picture(|pixel| Vec3::new1(pixel.count as f32))
+    }
+
+    fn picture(&self, value: impl Fn(&PixelStats) -> Vec3) -> FloatPicture {
+        let mut sol = FloatPicture::empty(self.width as u32, self.height as u32);
+        for (i, pixel) in self.stats.iter().enumerate() {
+            sol.set_pixel((i % self.width) as u32, (i / self.width) as u32, &value(pixel));
+        }
+        sol
+    }
+}
+
+//how display_progressive splits the samples into passes
+#[derive(Clone, Debug)]
+pub struct Progressive {
+    //samples every pixel gets per pass
+    pub pass_size: u32,
+    //the least time between two snapshots, None takes one after every pass
+    pub interval: Option<Duration>,
+}
+
+//func adds samples to the stats of a pixel until it has the given count, the linear radiance
+//is not clamped until FloatPicture::to_picture
+pub fn display<F>(func: F, input: Uniforms) -> Frame
+where F: Fn(usize, usize, &Uniforms, &mut PixelStats, u32) + Sync + Send {
+    let progressive = Progressive { pass_size: input.sample_count, interval: None };
+    display_progressive(func, input, &progressive, None, |_| {})
 }
 
-pub fn display<F>(func: F, input: Uniforms) -> Picture
-where F: Fn(usize, usize, &Uniforms) -> Pixel + Sync + Send {
-    let mut buffer = Picture::empty(WIDTH as u32, HEIGHT as u32);
+//renders a few samples per pixel at a time, snapshot gets the image so far between the passes,
+//or whenever the interval is over with the tiles finished until then
+//with a start frame the pixels keep their samples and only get the missing ones
+pub fn display_progressive<F, S>(func: F, mut input: Uniforms, progressive: &Progressive, start: Option<Frame>, mut snapshot: S) -> Frame
+where
+    F: Fn(usize, usize, &Uniforms, &mut PixelStats, u32) + Sync + Send,
+    S: FnMut(&Frame),
+{
+    assert!(progressive.pass_size > 0, "pass size must not be zero at display_progressive");
+    input.prepare();
+    let (width, height) = (input.cam.width, input.cam.height);
+    let mut frame = start.unwrap_or_else(|| Frame::new(width, height));
+    assert!(frame.width == width && frame.height == height, "the start frame has a different size than the image");
+    let mut tiles: Vec<TileStats> = tiles::tiles(width, height, input.tile_size, input.tile_order).into_iter()
+        .map(|tile| frame.tile_stats(tile))
+        .collect();
+    let passes = input.sample_count.div_ceil(progressive.pass_size);
+    let mut last_snapshot = Instant::now();
     println!("Rendering image...");
-    let pb = ProgressBar::new(HEIGHT as u64);
-    for y in 0..HEIGHT {
-        let row = parallel_row(&func, &input, y);
-        for (x, pix) in row.iter().enumerate() {
-            buffer.set_pixel(x as u32, y as u32, *pix);
+    let pb = ProgressBar::new(tiles.len() as u64 * passes as u64);
+    for pass in 1..=passes {
+        let until = (pass * progressive.pass_size).min(input.sample_count);
+        let work = std::mem::take(&mut tiles);
+        let count = work.len();
+        let mut done: Vec<(usize, TileStats)> = Vec::with_capacity(count);
+        let (sender, receiver) = mpsc::channel();
+        let (func, input) = (&func, &input);
+        std::thread::scope(|scope| {
+            //par_bridge hands the tiles out in order to whichever thread is free
+            scope.spawn(move || work.into_iter().enumerate().par_bridge().for_each_with(sender, |sender, (i, mut tile)| {
+                tile.render(func, input, until);
+                sender.send((i, tile)).expect("the render loop stopped receiving tiles");
+            }));
+            for (i, tile) in receiver {
+                frame.write_tile(&tile);
+                done.push((i, tile));
+                pb.inc(1);
+                let finished = pass == passes && done.len() == count;
+                if let Some(interval) = progressive.interval {
+                    if !finished && last_snapshot.elapsed() >= interval {
+                        pb.suspend(|| snapshot(&frame));
+                        last_snapshot = Instant::now();
+                    }
+                }
+            }
+        });
+        //the next pass goes through the tiles in the same order
+        done.sort_by_key(|(i, _)| *i);
+        tiles = done.into_iter().map(|(_, tile)| tile).collect();
+        if progressive.interval.is_none() && pass < passes {
+            pb.suspend(|| snapshot(&frame));
         }
-        pb.inc(1);
     }
     pb.finish_and_clear();
     println!("done!");
-    buffer
+    frame
+}
+
+//renders the pixels of one tile until they have `until` samples, for workers that get the tiles one by one
+//input needs to be prepared
+pub fn render_tile<F>(func: &F, input: &Uniforms, tile: &Tile, until: u32) -> Vec<PixelStats>
+where F: Fn(usize, usize, &Uniforms, &mut PixelStats, u32) + Sync + Send {
+    let pixels: Vec<(usize, usize)> = tile.pixels().collect();
+    let mut stats = vec![PixelStats::default(); pixels.len()];
+    stats.par_iter_mut().zip(pixels.par_iter()).for_each(|(stats, (x, y))|
+        func(*x, *y, input, stats, until)
+    );
+    stats
+}
+
+//running mean of the samples of a pixel, with the variance of their luminance (welford's algorithm)
+#[derive(Clone, Default)]
+pub struct PixelStats {
+    pub count: u32,
+    pub(crate) sum: Vec3,
+    pub(crate) mean: f32,
+    pub(crate) m2: f32,
+}
+
+impl PixelStats {
+    pub fn add(&mut self, color: &Vec3) {
+        self.count += 1;
+        self.sum = &self.sum + color;
+        let lum = luminance(color);
+        let delta = lum - self.mean;
+        self.mean += delta / self.count as f32;
+        self.m2 += delta * (lum - self.mean);
+    }
+
+    pub fn mean(&self) -> Vec3 {
+        if self.count == 0 {
+            return Vec3::default();
+        }
+        &self.sum / self.count as f32
+    }
+
+    //standard error of the mean luminance relative to the mean, dark pixels are measured against 0.01
+    pub fn relative_error(&self) -> f32 {
+        if self.count < 2 {
+            return f32::INFINITY;
+        }
+        let variance = self.m2 / (self.count - 1) as f32;
+        (variance / self.count as f32).sqrt() / self.mean.max(0.01)
+    }
 }
 
 
@@ -46,6 +205,7 @@ impl Pixel {
         Self{r, g, b}
     }
 
+    //values outside of 0-1 are clamped, no transfer function is applied
     pub fn from_vec(vec: Vec3) ->Self{
         let r = (vec.x*255.) as u8;
         let g = (vec.y*255.) as u8;
@@ -62,6 +222,62 @@ impl Pixel {
     }
 }
 
+//rgb framebuffer of f32 values, keeps radiance above 1 that an 8 bit Picture would clip
+#[derive(Clone, Debug)]
+pub struct FloatPicture {
+    pub width: u32,
+    pub height: u32,
+    data: Vec<f32>,
+}
+
+impl FloatPicture {
+    pub fn empty(width: u32, height: u32) -> Self {
+        Self { width, height, data: vec![0.; (width*height*3) as usize] }
+    }
+
+    fn get_first_index(&self, x: u32, y: u32) -> usize {
+        ((y*self.width + x)*3) as usize
+    }
+
+    pub fn get_pixel(&self, pos: (u32, u32)) -> Vec3 {
+        assert!(pos.0 < self.width && pos.1 < self.height, "x:{} or y:{}: is out of bounds", pos.0, pos.1);
+        let index = self.get_first_index(pos.0, pos.1);
+        Vec3::new(self.data[index], self.data[index+1], self.data[index+2])
+    }
+
+    pub fn set_pixel(&mut self, x: u32, y: u32, color: &Vec3) {
+        let index = self.get_first_index(x, y);
+        self.data[index] = color.x;
+        self.data[index+1] = color.y;
+        self.data[index+2] = color.z;
+    }
+
+    //interleaved rgb values, row by row from the top
+    pub fn data(&self) -> &[f32] {
+        &self.data
+    }
+
+    //divided by the largest value, so it fits into an 8 bit image
+    pub fn normalized(&self) -> Self {
+        let max = self.data.iter().fold(0f32, |m, v| m.max(*v));
+        let scale = if max > 0. { 1. / max } else { 1. };
+        Self { width: self.width, height: self.height, data: self.data.iter().map(|v| v * scale).collect() }
+    }
+
+    //the 8 bit conversion, everything above 1 is clipped
+    pub fn to_picture(&self, space: ColorSpace) -> Picture {
+        let mut sol = Picture::empty(self.width, self.height);
+        sol.color_space = space;
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let c = self.get_pixel((x, y));
+                sol.set_pixel(x, y, Pixel::new(space.encode(c.x), space.encode(c.y), space.encode(c.z)));
+            }
+        }
+        sol
+    }
+}
+
 #[derive(Clone, Debug)]
 enum Vecu8{
     Inmut(Arc<Vec<u8>>),
@@ -70,10 +286,7 @@ enum Vecu8{
 
 impl Vecu8{
     pub fn set(&mut self, index:usize, value: u8){
-        match self {
-            Self::Mutbl(vec) => {vec[index] = value},
-            _ => {}
-        }
+        if let Self::Mutbl(vec) = self {vec[index] = value}
     }
 }
 
@@ -105,17 +318,23 @@ impl Index<usize> for Vecu8{
 pub struct Picture {
     pub width: u32,
     pub height: u32,
+    pub color_space: ColorSpace,
     data: Vecu8,
 }
 
 impl Picture {
+    //images from disk are almost always srgb encoded
     pub fn new(img: RgbImage) -> Self {
+        Self::with_color_space(img, ColorSpace::Srgb)
+    }
+
+    pub fn with_color_space(img: RgbImage, color_space: ColorSpace) -> Self {
         let (width, height) = img.dimensions();
-        Self { width, height, data: Vecu8::Inmut(Arc::new(img.into_vec()))}
+        Self { width, height, color_space, data: Vecu8::Inmut(Arc::new(img.into_vec()))}
     }
 
     pub fn empty(width: u32, height: u32) ->Self {
-        Self { width, height, data: Vecu8::Mutbl(vec![0_u8; (width*height*3) as usize]) }
+        Self { width, height, color_space: ColorSpace::Srgb, data: Vecu8::Mutbl(vec![0_u8; (width*height*3) as usize]) }
     }
 
     pub fn get_first_index(&self, x:u32, y:u32) -> usize{
@@ -123,9 +342,10 @@ impl Picture {
         (x*3+y_index) as usize
     }
 
+    //linear 0-1 values, decoded according to the color space
     pub fn get_pixel_normalized(&self, pos: (u32, u32)) -> Vec3{
-        self.get_pixel(pos).to_vec()
-
+        let p = self.get_pixel(pos);
+        self.color_space.decode_vec(p.r, p.g, p.b)
     }
 
     pub fn get_pixel(&self, pos: (u32, u32)) ->Pixel{
@@ -164,10 +384,10 @@ impl Picture {
     }
 
     fn sigma(kernel: &Vec<Pixel>)->f32{
-        let average = Picture::avg_color(&kernel).brightness();
+        let average = Picture::avg_color(kernel).brightness();
         let mut diff: Vec<f32> = vec![];
         for i in kernel {
-            let d = &average-i.brightness();
+            let d = average-i.brightness();
             diff.push(d*d);
         }
         let mut avg_sqrt: f32 = 0.;
@@ -180,6 +400,7 @@ impl Picture {
 
     pub fn blur(&self, step: u32) -> Self {
         let mut sol = Self::empty(self.width, self.height);
+        sol.color_space = self.color_space;
         for y in 0..self.height {
             for x in 0..self.width {
                 sol.set_pixel(x, y, Self::avg_color(&self.get_kernel((x, y), step)));
@@ -212,6 +433,7 @@ impl Picture {
     pub fn denoise(&self, radius: u32)->Self{
         println!("Denoising...");
         let mut sol = Self::empty(self.width, self.height);
+        sol.color_space = self.color_space;
         let pb = ProgressBar::new((self.height*self.width) as u64);
         for y in 0..self.height {
             for x in 0..self.width {
@@ -0,0 +1,349 @@
+use std::f32::consts::PI;
+use std::str::FromStr;
+use crate::Vec3;
+
+//hands out the random numbers of one pixel sample, one dimension after the other
+//the same dimension of different samples of a pixel are spread evenly by the better samplers
+pub trait Sampler {
+    //starts sample `index` of pixel (x, y), the dimensions are counted from 0 again
+    fn start_sample(&mut self, x: usize, y: usize, index: u32);
+    //uniform in 0-1
+    fn get_1d(&mut self) -> f32;
+    fn get_2d(&mut self) -> (f32, f32);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum SamplerKind {
+    //white noise, every number is unrelated to the others
+    Independent,
+    //one jittered sample in each cell of a grid
+    Stratified,
+    //radical inverses with a prime base per dimension, owen scrambled per pixel
+    Halton,
+    //the first two sobol dimensions, owen scrambled and shuffled differently for every dimension
+    #[default]
+    Sobol,
+}
+
+impl SamplerKind {
+    pub const NAMES: [&'static str; 4] = ["independent", "stratified", "halton", "sobol"];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Independent => Self::NAMES[0],
+            Self::Stratified => Self::NAMES[1],
+            Self::Halton => Self::NAMES[2],
+            Self::Sobol => Self::NAMES[3],
+        }
+    }
+
+    //sample_count is the number of samples every pixel gets, the same seed always gives the same numbers
+    pub fn create(&self, sample_count: u32, seed: u64) -> Box<dyn Sampler> {
+        let count = sample_count.max(1);
+        let state = SampleState { seed, ..Default::default() };
+        match self {
+            Self::Independent => Box::new(Independent { state }),
+            Self::Stratified => Box::new(Stratified { count, state }),
+            Self::Halton => Box::new(Halton { state }),
+            Self::Sobol => Box::new(Sobol { state }),
+        }
+    }
+}
+
+impl FromStr for SamplerKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "independent" => Ok(Self::Independent),
+            "stratified" => Ok(Self::Stratified),
+            "halton" => Ok(Self::Halton),
+            "sobol" => Ok(Self::Sobol),
+            _ => Err(format!("unknown sampler '{}', available samplers: {}", s, Self::NAMES.join(", "))),
+        }
+    }
+}
+
+//uniform point on the unit disk, concentric mapping keeps neighbouring samples close
+pub fn sample_disk(u: (f32, f32)) -> (f32, f32) {
+    let (a, b) = (2. * u.0 - 1., 2. * u.1 - 1.);
+    if a == 0. && b == 0. {
+        return (0., 0.);
+    }
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, PI / 4. * (b / a))
+    } else {
+        (b, PI / 2. - PI / 4. * (a / b))
+    };
+    (r * theta.cos(), r * theta.sin())
+}
+
+//uniform direction, the same distribution as Vec3::random
+pub fn sample_sphere(u: (f32, f32)) -> Vec3 {
+    let z = 1. - 2. * u.0;
+    let r = (1. - z * z).max(0.).sqrt();
+    let phi = 2. * PI * u.1;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+fn mix(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846ca68b);
+    x ^= x >> 16;
+    x
+}
+
+fn hash(values: &[u32]) -> u32 {
+    values.iter().fold(0x9e3779b9, |h, v| mix(h ^ mix(v.wrapping_add(0x632be5ab))))
+}
+
+//the upper 24 bits as a float in 0-1, never reaches 1
+fn to_unit(bits: u32) -> f32 {
+    (bits >> 8) as f32 / (1 << 24) as f32
+}
+
+//element i of a random permutation of 0..len, https://graphics.pixar.com/library/MultiJitteredSampling/paper.pdf
+fn permute(mut i: u32, len: u32, p: u32) -> u32 {
+    let mut w = len - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < len {
+            break;
+        }
+    }
+    i.wrapping_add(p) % len
+}
+
+//owen scrambling in base 2, https://jcgt.org/published/0009/04/01/
+fn owen_scramble(x: u32, seed: u32) -> u32 {
+    let mut v = x.reverse_bits();
+    v = v.wrapping_add(seed);
+    v ^= v.wrapping_mul(0x6c50b47c);
+    v ^= v.wrapping_mul(0xb82f1e52);
+    v ^= v.wrapping_mul(0xc7afe638);
+    v ^= v.wrapping_mul(0x8d22f6e6);
+    v.reverse_bits()
+}
+
+//the pixel, the sample and the next dimension, every number is derived from them and the seed
+//so the result doesn't depend on which thread renders the pixel
+#[derive(Default)]
+struct SampleState {
+    seed: u64,
+    pixel: u32,
+    index: u32,
+    dim: u32,
+}
+
+impl SampleState {
+    fn start(&mut self, x: usize, y: usize, index: u32) {
+        self.pixel = hash(&[self.seed as u32, (self.seed >> 32) as u32, x as u32, y as u32]);
+        self.index = index;
+        self.dim = 0;
+    }
+
+    //a seed for the next dimension, the same for every sample of the pixel
+    fn next_seed(&mut self) -> u32 {
+        self.dim += 1;
+        hash(&[self.pixel, self.dim])
+    }
+}
+
+struct Independent {
+    state: SampleState,
+}
+
+impl Sampler for Independent {
+    fn start_sample(&mut self, x: usize, y: usize, index: u32) {
+        self.state.start(x, y, index);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let seed = self.state.next_seed();
+        to_unit(hash(&[seed, self.state.index]))
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        (self.get_1d(), self.get_1d())
+    }
+}
+
+struct Stratified {
+    count: u32,
+    state: SampleState,
+}
+
+impl Stratified {
+    //every sample of the pixel lands in a different cell, in a different order per dimension
+    fn cell(&mut self, cells: u32) -> (u32, u32) {
+        let seed = self.state.next_seed();
+        (permute(self.state.index % self.count, cells, seed), hash(&[seed, self.state.index]))
+    }
+}
+
+impl Sampler for Stratified {
+    fn start_sample(&mut self, x: usize, y: usize, index: u32) {
+        self.state.start(x, y, index);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let (cell, jitter) = self.cell(self.count);
+        ((cell as f32 + to_unit(jitter)) / self.count as f32).min(1. - f32::EPSILON)
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        //the grid has at least count cells, the extra ones stay empty
+        let nx = (self.count as f32).sqrt().ceil() as u32;
+        let ny = self.count.div_ceil(nx);
+        let (cell, jitter) = self.cell(nx * ny);
+        let x = ((cell % nx) as f32 + to_unit(jitter)) / nx as f32;
+        let y = ((cell / nx) as f32 + to_unit(mix(jitter))) / ny as f32;
+        (x.min(1. - f32::EPSILON), y.min(1. - f32::EPSILON))
+    }
+}
+
+const PRIMES: [u32; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53,
+    59, 61, 67, 71, 73, 79, 83, 89, 97, 101, 103, 107, 109, 113, 127, 131,
+];
+
+//the digits of index in the base, mirrored behind the point and owen scrambled:
+//every digit is permuted depending on the digits before it, so the points stay stratified
+fn scrambled_radical_inverse(base: u32, mut index: u32, seed: u32) -> f32 {
+    let inv_base = 1. / base as f64;
+    let mut factor = inv_base;
+    let mut prefix: u64 = 0;
+    let mut place: u64 = 1;
+    let mut v = 0.;
+    //past the digits of index the zeros are scrambled too, until f32 can't tell the difference
+    for pos in 0.. {
+        if factor < 1e-8 {
+            break;
+        }
+        let digit = index % base;
+        let permuted = permute(digit, base, hash(&[seed, pos, prefix as u32]));
+        v += permuted as f64 * factor;
+        prefix += digit as u64 * place;
+        place = (place * base as u64).min(1 << 32);
+        index /= base;
+        factor *= inv_base;
+    }
+    v as f32
+}
+
+struct Halton {
+    state: SampleState,
+}
+
+impl Sampler for Halton {
+    fn start_sample(&mut self, x: usize, y: usize, index: u32) {
+        self.state.start(x, y, index);
+    }
+
+    //dimensions past the prime table reuse the bases with a different scramble
+    fn get_1d(&mut self) -> f32 {
+        let base = PRIMES[self.state.dim as usize % PRIMES.len()];
+        let seed = self.state.next_seed();
+        scrambled_radical_inverse(base, self.state.index, seed).min(1. - f32::EPSILON)
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        (self.get_1d(), self.get_1d())
+    }
+}
+
+//the first two dimensions of the sobol sequence as 32 bit fractions
+fn sobol(index: u32) -> (u32, u32) {
+    let mut y = 0;
+    let mut v: u32 = 1 << 31;
+    let mut i = index;
+    while i != 0 {
+        if i & 1 != 0 {
+            y ^= v;
+        }
+        i >>= 1;
+        v ^= v >> 1;
+    }
+    (index.reverse_bits(), y)
+}
+
+struct Sobol {
+    state: SampleState,
+}
+
+impl Sobol {
+    fn next(&mut self) -> (u32, u32, u32) {
+        let seed = self.state.next_seed();
+        let (x, y) = sobol(owen_scramble(self.state.index, seed));
+        (x, y, seed)
+    }
+}
+
+impl Sampler for Sobol {
+    fn start_sample(&mut self, x: usize, y: usize, index: u32) {
+        self.state.start(x, y, index);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let (x, _, seed) = self.next();
+        to_unit(owen_scramble(x, mix(seed)))
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        let (x, y, seed) = self.next();
+        (to_unit(owen_scramble(x, mix(seed))), to_unit(owen_scramble(y, mix(seed ^ 1))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //a few dimensions of every sample of one pixel
+    fn sequence(kind: SamplerKind, seed: u64) -> Vec<f32> {
+        let mut sampler = kind.create(16, seed);
+        let mut values = vec![];
+        for index in 0..16 {
+            sampler.start_sample(3, 7, index);
+            values.push(sampler.get_1d());
+            let (a, b) = sampler.get_2d();
+            values.extend([a, b]);
+        }
+        values
+    }
+
+    #[test]
+    fn same_seed_gives_same_numbers() {
+        for name in SamplerKind::NAMES {
+            let kind: SamplerKind = name.parse().unwrap();
+            let values = sequence(kind, 42);
+            assert_eq!(values, sequence(kind, 42), "{} is not deterministic", name);
+            assert_ne!(values, sequence(kind, 43), "{} ignores the seed", name);
+            assert!(values.iter().all(|v| (0. ..1.).contains(v)), "{} left 0-1", name);
+        }
+    }
+}
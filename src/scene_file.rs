@@ -0,0 +1,686 @@
+//plain text scenes, one directive per line followed by its `key value...` pairs, # starts a comment
+//scenes/reference.scene uses every directive, paths of images and meshes are relative to the scene file
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use image::ImageReader;
+use crate::{
+    Uniforms, Adaptive, Camera, EnvShader, DEFAULT_WIDTH, DEFAULT_HEIGHT,
+    bvh::Aabb,
+    mesh,
+    bsdf::ConductorKind,
+    geometry::{Material, Principled, Reflection},
+    medium::{Atmosphere, Medium},
+    objects::{Object, QuadType, abstract_object},
+    render::Picture,
+    sampler::SamplerKind,
+    spectrum::Ior,
+    tiles::TileOrder,
+    textures::Texture,
+    transform::Transform,
+    vec3::Vec3,
+};
+
+//the material keys of the principled parameters
+const PRINCIPLED_KEYS: [&str; 6] = ["metallic", "roughness", "specular", "clearcoat", "sheen", "transmission"];
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io { path: PathBuf, err: std::io::Error },
+    Parse { line: usize, msg: String },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { path, err } => write!(f, "could not read {}: {}", path.display(), err),
+            Self::Parse { line, msg } => write!(f, "line {}: {}", line, msg),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+pub fn load(path: impl AsRef<Path>) -> Result<Uniforms, SceneError> {
+    let path = path.as_ref();
+    let src = std::fs::read_to_string(path).map_err(|err| SceneError::Io { path: path.to_path_buf(), err })?;
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+    parse(&src, base_dir)
+}
+
+//the key-value pairs of one directive, split at the keys the directive knows about
+struct Fields<'a> {
+    line: usize,
+    directive: &'a str,
+    values: HashMap<&'a str, Vec<&'a str>>,
+}
+
+impl<'a> Fields<'a> {
+    fn new(line: usize, directive: &'a str, tokens: &[&'a str], keys: &[&str]) -> Result<Self, SceneError> {
+        let mut values: HashMap<&str, Vec<&str>> = HashMap::new();
+        let mut current: Option<&str> = None;
+        for tok in tokens {
+            if keys.contains(tok) {
+                if values.contains_key(tok) {
+                    return Err(err(line, format!("'{}' is given twice for {}", tok, directive)));
+                }
+                values.insert(tok, vec![]);
+                current = Some(tok);
+            } else if let Some(key) = current {
+                values.get_mut(key).unwrap().push(tok);
+            } else {
+                return Err(err(line, format!("unexpected '{}', {} expects the keys: {}", tok, directive, keys.join(", "))));
+            }
+        }
+        Ok(Self { line, directive, values })
+    }
+
+    fn err(&self, msg: String) -> SceneError {
+        err(self.line, msg)
+    }
+
+    fn has(&self, key: &str) -> bool {
+        self.values.contains_key(key)
+    }
+
+    fn raw(&self, key: &str) -> Result<&[&'a str], SceneError> {
+        self.values.get(key).map(|v| v.as_slice()).ok_or(self.err(format!("{} is missing '{}'", self.directive, key)))
+    }
+
+    fn floats(&self, key: &str, count: usize) -> Result<Vec<f32>, SceneError> {
+        let raw = self.raw(key)?;
+        if raw.len() != count {
+            return Err(self.err(format!("'{}' expects {} number(s), found {}", key, count, raw.len())));
+        }
+        raw.iter().map(|t| number(self.line, t)).collect()
+    }
+
+    fn f32(&self, key: &str) -> Result<f32, SceneError> {
+        Ok(self.floats(key, 1)?[0])
+    }
+
+    fn f32_or(&self, key: &str, default: f32) -> Result<f32, SceneError> {
+        if self.has(key) { self.f32(key) } else { Ok(default) }
+    }
+
+    fn vec3(&self, key: &str) -> Result<Vec3, SceneError> {
+        let f = self.floats(key, 3)?;
+        Ok(Vec3::new(f[0], f[1], f[2]))
+    }
+
+    //a size, zero or less would give objects without area
+    fn positive(&self, key: &str) -> Result<f32, SceneError> {
+        let v = self.f32(key)?;
+        if !v.is_finite() || v <= 0. {
+            return Err(self.err(format!("{} '{}' must be greater than zero", self.directive, key)));
+        }
+        Ok(v)
+    }
+
+    //a direction, a zero one has no normalized form
+    fn direction(&self, key: &str) -> Result<Vec3, SceneError> {
+        let v = self.vec3(key)?;
+        let len = v.length_squared();
+        if len == 0. || !len.is_finite() {
+            return Err(self.err(format!("{} '{}' must not be zero", self.directive, key)));
+        }
+        Ok(v)
+    }
+
+    fn vec3_or(&self, key: &str, default: Vec3) -> Result<Vec3, SceneError> {
+        if self.has(key) { self.vec3(key) } else { Ok(default) }
+    }
+
+    fn word(&self, key: &str) -> Result<&'a str, SceneError> {
+        match self.raw(key)? {
+            [w] => Ok(w),
+            other => Err(self.err(format!("'{}' expects a single word, found {}", key, other.len()))),
+        }
+    }
+}
+
+//the keys of volume and atmosphere
+fn medium(f: &Fields) -> Result<Medium, SceneError> {
+    let absorption = f.vec3_or("absorption", Vec3::default())?;
+    let scattering = f.vec3_or("scattering", Vec3::default())?;
+    if [&absorption, &scattering].iter().any(|c| c.x < 0. || c.y < 0. || c.z < 0.) {
+        return Err(f.err(format!("{} 'absorption' and 'scattering' must not be negative", f.directive)));
+    }
+    let g = f.f32_or("g", 0.)?;
+    if g.is_nan() || g.abs() >= 1. {
+        return Err(f.err(format!("{} 'g' must be between -1 and 1", f.directive)));
+    }
+    Ok(Medium { absorption, scattering, g })
+}
+
+fn err(line: usize, msg: String) -> SceneError {
+    SceneError::Parse { line, msg }
+}
+
+fn number<T: std::str::FromStr>(line: usize, tok: &str) -> Result<T, SceneError> {
+    tok.parse().map_err(|_| err(line, format!("'{}' is not a valid number", tok)))
+}
+
+fn vec3_from(line: usize, toks: &[&str]) -> Result<Vec3, SceneError> {
+    if toks.len() != 3 {
+        return Err(err(line, format!("expected 3 numbers for a vector, found {}", toks.len())));
+    }
+    Ok(Vec3::new(number(line, toks[0])?, number(line, toks[1])?, number(line, toks[2])?))
+}
+
+enum Block {
+    Group(Option<Aabb>),
+    Define(String),
+    Volume(Medium),
+}
+
+struct Parser<'a> {
+    base_dir: &'a Path,
+    materials: HashMap<String, Material>,
+    images: HashMap<PathBuf, Picture>,
+    //open groups and definitions, the last one receives new objects
+    groups: Vec<(usize, Block, Vec<Object>)>,
+    definitions: HashMap<String, Arc<Object>>,
+    objects: Vec<Object>,
+    camera: Option<(Vec3, Vec3, f32, Vec3, f32)>,
+    resolution: (usize, usize),
+    sample_count: u32,
+    adaptive: Option<Adaptive>,
+    bounce_count: u8,
+    min_bounce_count: u8,
+    sampler: SamplerKind,
+    seed: u64,
+    spectral: bool,
+    atmosphere: Option<Atmosphere>,
+    tile_size: usize,
+    tile_order: TileOrder,
+    offset: Option<f32>,
+    env_shader: Option<EnvShader>,
+}
+
+impl<'a> Parser<'a> {
+    fn material(&self, f: &Fields) -> Result<Material, SceneError> {
+        if !f.has("mat") {
+            return Ok(Material::default());
+        }
+        let name = f.word("mat")?;
+        self.materials.get(name).cloned().ok_or(f.err(format!("unknown material '{}'", name)))
+    }
+
+    fn push(&mut self, mut objs: Vec<Object>) {
+        match self.groups.last_mut() {
+            Some((_, _, inside)) => inside.append(&mut objs),
+            None => self.objects.append(&mut objs),
+        }
+    }
+
+    fn texture(&mut self, f: &Fields) -> Result<Texture, SceneError> {
+        if !f.has("tex") {
+            return Ok(Material::default().tex);
+        }
+        self.texture_at(f, "tex")
+    }
+
+    //a principled parameter, a single number or a texture
+    fn param(&mut self, f: &Fields, key: &str, default: &Texture) -> Result<Texture, SceneError> {
+        if !f.has(key) {
+            return Ok(default.clone());
+        }
+        match f.raw(key)? {
+            [v] => Ok(Texture::Solid { color: Vec3::new1(number(f.line, v)?) }),
+            _ => self.texture_at(f, key),
+        }
+    }
+
+    fn texture_at(&mut self, f: &Fields, key: &str) -> Result<Texture, SceneError> {
+        let raw = f.raw(key)?;
+        match raw.first().copied() {
+            Some("solid") => Ok(Texture::Solid { color: vec3_from(f.line, &raw[1..])? }),
+            Some("checker") if raw.len() == 8 => Ok(Texture::Checker {
+                color1: vec3_from(f.line, &raw[1..4])?,
+                color2: vec3_from(f.line, &raw[4..7])?,
+                size: number(f.line, raw[7])?,
+            }),
+            Some("checker") => Err(f.err("checker expects two colors and a size".to_string())),
+            Some("image") if raw.len() == 2 => {
+                let path = self.base_dir.join(raw[1]);
+                if let Some(img) = self.images.get(&path) {
+                    return Ok(Texture::Img { img: img.clone() });
+                }
+                let decoded = ImageReader::open(&path)
+                    .map_err(|e| f.err(format!("could not open image {}: {}", path.display(), e)))?
+                    .decode()
+                    .map_err(|e| f.err(format!("could not decode image {}: {}", path.display(), e)))?;
+                let img = Picture::new(decoded.into_rgb8());
+                self.images.insert(path, img.clone());
+                Ok(Texture::Img { img })
+            }
+            Some("image") => Err(f.err("image expects a single file path".to_string())),
+            _ => Err(f.err("unknown texture, expected solid, checker or image".to_string())),
+        }
+    }
+
+    fn reflection(&mut self, f: &Fields) -> Result<Reflection, SceneError> {
+        let principled = matches!(f.raw("refl"), Ok(["principled", ..]));
+        if let Some(key) = PRINCIPLED_KEYS.iter().find(|k| f.has(k)).filter(|_| !principled) {
+            return Err(f.err(format!("'{}' only applies to principled materials", key)));
+        }
+        if !f.has("refl") {
+            return Ok(Reflection::Diffuse());
+        }
+        let refl = f.raw("refl")?;
+        if let ["glass", name] = refl {
+            if let Some(ior) = Ior::preset(name) {
+                return Ok(Reflection::Dispersive { ior });
+            }
+        }
+        match refl {
+            ["diffuse"] => Ok(Reflection::Diffuse()),
+            ["metal", r] => Ok(Reflection::Metal { roughness: number(f.line, r)? }),
+            ["glass", ior] => Ok(Reflection::Glass { reflective: number(f.line, ior)? }),
+            ["glass", "cauchy", a, b] => Ok(Reflection::Dispersive { ior: Ior::Cauchy { a: number(f.line, a)?, b: number(f.line, b)? } }),
+            ["glass", "sellmeier", rest @ ..] if rest.len() == 6 => {
+                let v = rest.iter().map(|t| number(f.line, t)).collect::<Result<Vec<f32>, SceneError>>()?;
+                Ok(Reflection::Dispersive { ior: Ior::Sellmeier { b: [v[0], v[1], v[2]], c: [v[3], v[4], v[5]] } })
+            }
+            ["conductor", kind, r] => {
+                let kind: ConductorKind = kind.parse().map_err(|e| f.err(e))?;
+                Ok(Reflection::conductor(kind, number(f.line, r)?))
+            }
+            ["principled", rest @ ..] if rest.len() <= 1 => {
+                let d = Principled::default();
+                Ok(Reflection::Principled(Box::new(Principled {
+                    metallic: self.param(f, "metallic", &d.metallic)?,
+                    roughness: self.param(f, "roughness", &d.roughness)?,
+                    specular: self.param(f, "specular", &d.specular)?,
+                    clearcoat: self.param(f, "clearcoat", &d.clearcoat)?,
+                    sheen: self.param(f, "sheen", &d.sheen)?,
+                    transmission: self.param(f, "transmission", &d.transmission)?,
+                    ior: match rest {
+                        [ior] => number(f.line, ior)?,
+                        _ => d.ior,
+                    },
+                })))
+            }
+            _ => Err(f.err(format!(
+                "unknown reflection, expected diffuse, metal <roughness>, glass <ior>, glass cauchy <a> <b>, glass sellmeier <b1 b2 b3 c1 c2 c3>, glass <{}>, conductor <metal> <roughness> or principled [ior]",
+                Ior::NAMES.join("|")
+            ))),
+        }
+    }
+
+    fn line(&mut self, line: usize, tokens: &[&str]) -> Result<(), SceneError> {
+        let (directive, rest) = (tokens[0], &tokens[1..]);
+        let fields = |keys: &[&str]| Fields::new(line, directive, rest, keys);
+        match directive {
+            "samples" => {
+                let count: u32 = single(line, directive, rest)?;
+                if count == 0 {
+                    return Err(err(line, "samples must be at least 1".to_string()));
+                }
+                self.sample_count = count;
+            }
+            "bounces" => self.bounce_count = single(line, directive, rest)?,
+            "min_bounces" => self.min_bounce_count = single(line, directive, rest)?,
+            "adaptive" => {
+                let f = fields(&["threshold", "min"])?;
+                let defaults = Adaptive::default();
+                let threshold = f.f32_or("threshold", defaults.threshold)?;
+                if threshold.is_nan() || threshold <= 0. {
+                    return Err(f.err("adaptive 'threshold' must be greater than zero".to_string()));
+                }
+                let min_sample_count = if f.has("min") { number(line, f.word("min")?)? } else { defaults.min_sample_count };
+                self.adaptive = Some(Adaptive { threshold, min_sample_count });
+            }
+            "tiles" => {
+                let f = fields(&["size", "order"])?;
+                if f.has("size") {
+                    let size: usize = number(line, f.word("size")?)?;
+                    if size == 0 {
+                        return Err(f.err("tile 'size' must not be zero".to_string()));
+                    }
+                    self.tile_size = size;
+                }
+                if f.has("order") {
+                    self.tile_order = f.word("order")?.parse().map_err(|msg| f.err(msg))?;
+                }
+            }
+            "seed" => self.seed = single(line, directive, rest)?,
+            "spectral" => match rest {
+                [v] => self.spectral = boolean(line, v)?,
+                _ => return Err(err(line, "spectral expects true or false".to_string())),
+            },
+            "atmosphere" => {
+                let f = fields(&["absorption", "scattering", "g", "depth"])?;
+                let depth = f.f32_or("depth", Atmosphere::DEFAULT_DEPTH)?;
+                if depth.is_nan() || depth < 0. {
+                    return Err(f.err("atmosphere 'depth' must not be negative".to_string()));
+                }
+                self.atmosphere = Some(Atmosphere { medium: medium(&f)?, depth });
+            }
+            "sampler" => match rest {
+                [name] => self.sampler = name.parse().map_err(|msg| err(line, msg))?,
+                _ => return Err(err(line, format!("sampler expects one of: {}", SamplerKind::NAMES.join(", ")))),
+            },
+            "offset" => self.offset = Some(single(line, directive, rest)?),
+            "resolution" => match rest {
+                [w, h] => {
+                    let res: (usize, usize) = (number(line, w)?, number(line, h)?);
+                    if res.0 == 0 || res.1 == 0 {
+                        return Err(err(line, "resolution must not be zero".to_string()));
+                    }
+                    self.resolution = res;
+                }
+                _ => return Err(err(line, "resolution expects a width and a height".to_string())),
+            },
+            "camera" => {
+                let f = fields(&["from", "at", "fov", "up", "blur"])?;
+                let up = f.vec3_or("up", Vec3::up())?;
+                if !up.is_normalized() {
+                    return Err(f.err("camera 'up' must be a unit vector".to_string()));
+                }
+                let from = f.vec3("from")?;
+                let at = f.vec3("at")?;
+                let dist = (&from - &at).length_squared();
+                if dist == 0. || !dist.is_finite() {
+                    return Err(f.err("camera 'from' and 'at' must differ".to_string()));
+                }
+                if (&from - &at).normalize().dot(&up).abs() >= 0.999 {
+                    return Err(f.err("camera 'up' must not be parallel to the viewing direction".to_string()));
+                }
+                let fov = f.f32("fov")?;
+                if !(fov > 0. && fov < 180.) {
+                    return Err(f.err("camera 'fov' must be between 0 and 180 degrees".to_string()));
+                }
+                self.camera = Some((from, at, fov, up, f.f32_or("blur", 0.)?));
+            }
+            "environment" => {
+                self.env_shader = Some(match rest {
+                    ["solid", col @ ..] => {
+                        let color = vec3_from(line, col)?;
+                        Box::new(move |_: &Vec3| color.clone())
+                    }
+                    ["gradient", cols @ ..] if cols.len() == 6 => {
+                        let horizon = vec3_from(line, &cols[..3])?;
+                        let zenith = vec3_from(line, &cols[3..])?;
+                        Box::new(move |v: &Vec3| horizon.lerp(&zenith, v.dot(&Vec3::up()).clamp(0., 1.)))
+                    }
+                    _ => return Err(err(line, "environment expects 'solid <color>' or 'gradient <horizon color> <zenith color>'".to_string())),
+                });
+            }
+            "material" => {
+                let Some((name, rest)) = rest.split_first() else {
+                    return Err(err(line, "material needs a name".to_string()));
+                };
+                let keys = [["refl", "tex", "emit"].as_slice(), &PRINCIPLED_KEYS].concat();
+                let f = Fields::new(line, directive, rest, &keys)?;
+                let mat = Material::new(self.reflection(&f)?, self.texture(&f)?, f.vec3_or("emit", Vec3::default())?);
+                self.materials.insert(name.to_string(), mat);
+            }
+            "sphere" => {
+                let f = fields(&["pos", "rad", "mat"])?;
+                let obj = Object::Sphere { pos: f.vec3("pos")?, rad: f.positive("rad")?, mat: self.material(&f)? };
+                self.push(vec![obj]);
+            }
+            "plane" => {
+                let f = fields(&["pos", "normal", "mat"])?;
+                let normal = f.vec3("normal")?;
+                if normal.length_squared() == 0. {
+                    return Err(f.err("plane normal must not be zero".to_string()));
+                }
+                let obj = Object::Plane { pos: f.vec3("pos")?, normal: normal.normalize(), mat: self.material(&f)? };
+                self.push(vec![obj]);
+            }
+            "quad" => {
+                let Some((kind, rest)) = rest.split_first() else {
+                    return Err(err(line, "quad needs a kind: rect, triangle or disk".to_string()));
+                };
+                let kind = match *kind {
+                    "rect" => QuadType::Rect(),
+                    "triangle" => QuadType::Triangle(),
+                    "disk" => QuadType::Disk(),
+                    other => return Err(err(line, format!("unknown quad kind '{}', expected rect, triangle or disk", other))),
+                };
+                let f = Fields::new(line, directive, rest, &["pos", "dx", "dy", "mat"])?;
+                let (dx, dy) = (f.vec3("dx")?, f.vec3("dy")?);
+                let area = dx.cross(&dy).length_squared();
+                if area == 0. || !area.is_finite() {
+                    return Err(f.err("quad 'dx' and 'dy' must not be zero or parallel".to_string()));
+                }
+                let obj = Object::new_quad(f.vec3("pos")?, dx, dy, kind, self.material(&f)?);
+                self.push(vec![obj]);
+            }
+            "cylinder" => {
+                let f = fields(&["pos", "dy", "faces", "radius", "fill", "mat"])?;
+                let faces: u32 = number(line, f.word("faces")?)?;
+                if !(3..=360).contains(&faces) {
+                    return Err(f.err("cylinder needs between 3 and 360 faces".to_string()));
+                }
+                let fill = if f.has("fill") { boolean(line, f.word("fill")?)? } else { true };
+                let objs = abstract_object::new_cylinder(&f.vec3("pos")?, &f.direction("dy")?, faces, f.positive("radius")?, fill, self.material(&f)?);
+                self.push(objs);
+            }
+            "pyramid" => {
+                let f = fields(&["pos", "size", "dy", "rotation", "mat"])?;
+                let objs = abstract_object::new_pyramid(&f.vec3("pos")?, f.positive("size")?, &f.direction("dy")?, f.f32_or("rotation", 0.)?, self.material(&f)?);
+                self.push(objs);
+            }
+            "box" => {
+                let f = fields(&["pos", "dy", "size", "mat"])?;
+                let objs = abstract_object::new_box(&f.vec3("pos")?, &f.direction("dy")?, f.positive("size")?, self.material(&f)?);
+                self.push(objs);
+            }
+            "mesh" => {
+                let f = fields(&["file", "mat"])?;
+                let path = self.base_dir.join(f.word("file")?);
+                let mut mesh = mesh::load_obj(&path).map_err(|e| f.err(e.to_string()))?;
+                if f.has("mat") {
+                    mesh.set_material(self.material(&f)?);
+                }
+                self.push(vec![mesh.into_object()]);
+            }
+            "group" => {
+                let f = fields(&["min", "max"])?;
+                let bounds = match (f.has("min"), f.has("max")) {
+                    (false, false) => None,
+                    _ => Some(Aabb::new(f.vec3("min")?, f.vec3("max")?)),
+                };
+                self.groups.push((line, Block::Group(bounds), vec![]));
+            }
+            "volume" => {
+                let f = fields(&["absorption", "scattering", "g"])?;
+                self.groups.push((line, Block::Volume(medium(&f)?), vec![]));
+            }
+            "define" => {
+                let [name] = rest else {
+                    return Err(err(line, "define expects a single name".to_string()));
+                };
+                if self.definitions.contains_key(*name) || self.groups.iter().any(|(_, b, _)| matches!(b, Block::Define(n) if n == name)) {
+                    return Err(err(line, format!("'{}' is already defined", name)));
+                }
+                self.groups.push((line, Block::Define(name.to_string()), vec![]));
+            }
+            "instance" => {
+                let Some((name, rest)) = rest.split_first() else {
+                    return Err(err(line, "instance needs the name of a definition".to_string()));
+                };
+                let obj = self.definitions.get(*name).ok_or(err(line, format!("unknown definition '{}'", name)))?.clone();
+                let f = Fields::new(line, directive, rest, &["scale", "rotate", "translate"])?;
+                let scale = f.vec3_or("scale", Vec3::new1(1.))?;
+                if scale.x == 0. || scale.y == 0. || scale.z == 0. {
+                    return Err(f.err("scale must not be zero".to_string()));
+                }
+                let rot = f.vec3_or("rotate", Vec3::default())?;
+                let transform = Transform::scale(&scale)
+                    .then(&Transform::rotate_x(rot.x))
+                    .then(&Transform::rotate_y(rot.y))
+                    .then(&Transform::rotate_z(rot.z))
+                    .then(&Transform::translate(&f.vec3_or("translate", Vec3::default())?));
+                self.push(vec![Object::new_instance(&obj, transform)]);
+            }
+            "end" => {
+                if !rest.is_empty() {
+                    return Err(err(line, "end takes no arguments".to_string()));
+                }
+                let Some((start, block, inside)) = self.groups.pop() else {
+                    return Err(err(line, "'end' without an open group, define or volume".to_string()));
+                };
+                let bounds = match block {
+                    Block::Group(bounds) => bounds,
+                    Block::Define(name) => {
+                        if inside.is_empty() {
+                            return Err(err(start, format!("definition '{}' is empty", name)));
+                        }
+                        self.definitions.insert(name, Arc::new(Object::new_bvh(inside)));
+                        return Ok(());
+                    }
+                    Block::Volume(medium) => {
+                        if inside.is_empty() {
+                            return Err(err(start, "volume is empty".to_string()));
+                        }
+                        if Object::bounds_of(&inside).is_none() {
+                            return Err(err(start, "volume can not contain planes".to_string()));
+                        }
+                        self.push(vec![Object::new_volume(inside, medium)]);
+                        return Ok(());
+                    }
+                };
+                let group = match bounds {
+                    Some(Aabb { min, max }) => Object::BoundBox { min, max, inside },
+                    None if inside.is_empty() => return Err(err(start, "group is empty".to_string())),
+                    None if Object::bounds_of(&inside).is_none() => {
+                        return Err(err(start, "group without min and max can not contain planes".to_string()))
+                    }
+                    None => Object::new_bound_box(inside),
+                };
+                self.push(vec![group]);
+            }
+            other => return Err(err(line, format!("unknown directive '{}'", other))),
+        }
+        Ok(())
+    }
+}
+
+fn single<T: std::str::FromStr>(line: usize, directive: &str, rest: &[&str]) -> Result<T, SceneError> {
+    match rest {
+        [v] => number(line, v),
+        _ => Err(err(line, format!("{} expects a single number", directive))),
+    }
+}
+
+fn boolean(line: usize, tok: &str) -> Result<bool, SceneError> {
+    match tok {
+        "true" | "yes" | "1" => Ok(true),
+        "false" | "no" | "0" => Ok(false),
+        other => Err(err(line, format!("'{}' is not true or false", other))),
+    }
+}
+
+//base_dir is used to resolve image paths
+pub fn parse(src: &str, base_dir: &Path) -> Result<Uniforms, SceneError> {
+    let defaults = Uniforms::default();
+    let mut parser = Parser {
+        base_dir,
+        materials: HashMap::from([("default".to_string(), Material::default())]),
+        images: HashMap::new(),
+        groups: vec![],
+        definitions: HashMap::new(),
+        objects: vec![],
+        camera: None,
+        resolution: (DEFAULT_WIDTH, DEFAULT_HEIGHT),
+        sample_count: defaults.sample_count,
+        adaptive: defaults.adaptive,
+        bounce_count: defaults.bounce_count,
+        min_bounce_count: defaults.min_bounce_count,
+        sampler: defaults.sampler,
+        seed: defaults.seed,
+        spectral: defaults.spectral,
+        atmosphere: None,
+        tile_size: defaults.tile_size,
+        tile_order: defaults.tile_order,
+        offset: None,
+        env_shader: None,
+    };
+    for (i, text) in src.lines().enumerate() {
+        let text = text.split('#').next().unwrap_or("");
+        let tokens: Vec<&str> = text.split_whitespace().collect();
+        if !tokens.is_empty() {
+            parser.line(i + 1, &tokens)?;
+        }
+    }
+    if let Some((line, ..)) = parser.groups.last() {
+        return Err(err(*line, "block is never closed with 'end'".to_string()));
+    }
+    let (width, height) = parser.resolution;
+    let cam = match parser.camera {
+        Some((from, at, fov, up, blur)) => Camera::new(&from, &at, fov, &up, blur, width, height),
+        None => {
+            let mut cam = Camera::default();
+            cam.set_resolution(width, height);
+            cam
+        }
+    };
+    Ok(Uniforms {
+        sample_count: parser.sample_count,
+        adaptive: parser.adaptive,
+        bounce_count: parser.bounce_count,
+        min_bounce_count: parser.min_bounce_count,
+        sampler: parser.sampler,
+        seed: parser.seed,
+        spectral: parser.spectral,
+        atmosphere: parser.atmosphere,
+        tile_size: parser.tile_size,
+        tile_order: parser.tile_order,
+        offset: parser.offset.unwrap_or(DEFAULT_WIDTH as f32 / 1000.),
+        cam,
+        objects: parser.objects,
+        env_shader: parser.env_shader.unwrap_or_else(Uniforms::get_env_shader),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //the line of the error parsing src stops at
+    fn error_line(src: &str) -> usize {
+        match parse(src, Path::new(".")) {
+            Err(SceneError::Parse { line, .. }) => line,
+            Err(e) => panic!("unexpected error {}", e),
+            Ok(_) => panic!("{:?} parsed", src),
+        }
+    }
+
+    #[test]
+    fn errors_name_their_line() {
+        assert_eq!(error_line("samples 4\n\n# comment\nsphere pos 0 0 0\n"), 4);
+        assert_eq!(error_line("bounces 5\nteapot\n"), 2);
+        assert_eq!(error_line("material a refl glass\nsphere pos 0 0 0 rad 1 mat b\n"), 1);
+        assert_eq!(error_line("samples 2\ngroup\nsphere pos 0 0 0 rad 1\n"), 2);
+        assert_eq!(error_line("sphere pos 0 0 0 rad 1 mat b\n"), 1);
+        assert_eq!(error_line("samples 4\n\nsamples 0\n"), 3);
+        assert_eq!(error_line("samples 4\ncamera from 0 0 0 at 0 0 0 fov 90\n"), 2);
+        assert_eq!(error_line("quad rect pos 0 0 0 dx 1 0 0 dy 2 0 0\n"), 1);
+        assert_eq!(error_line("cylinder pos 0 0 0 dy 0 0 1 faces 8 radius 0\n"), 1);
+        assert_eq!(error_line("box pos 0 0 0 dy 0 0 1 size -1\n"), 1);
+        assert_eq!(error_line("pyramid pos 0 0 0 dy 0 0 0 size 1\n"), 1);
+    }
+
+    #[test]
+    fn scene_settings_are_read() {
+        let Ok(uni) = parse("samples 12\nseed 3\nspectral true\nsphere pos 0 0 0 rad 1\n", Path::new(".")) else {
+            panic!("the scene did not parse")
+        };
+        assert_eq!((uni.sample_count, uni.seed, uni.spectral, uni.objects.len()), (12, 3, true, 1));
+    }
+
+    #[test]
+    fn reference_scene_loads() {
+        if let Err(e) = load(Path::new("scenes/reference.scene")) {
+            panic!("scenes/reference.scene: {}", e);
+        }
+    }
+}
@@ -1,7 +1,9 @@
 #![allow(dead_code)]
 use image::{RgbImage, ImageReader};
+use rand::{SeedableRng, rngs::StdRng};
 use raytracing::{	
-	WIDTH,
+	DEFAULT_WIDTH,
+	DEFAULT_HEIGHT,
 	textures::Texture,
 	geometry::{Material, Reflection, Camera}, 
 	Uniforms, 
@@ -10,14 +12,21 @@ use raytracing::{
     render::Picture
 };
 
-pub fn lalaland()-> Uniforms{
-    let image: RgbImage = ImageReader::open("moon3.jpg").unwrap().decode().unwrap().into_rgb8();
+//the moon texture is read from the working directory
+pub fn lalaland()-> Result<Uniforms, String>{
+    let image: RgbImage = ImageReader::open("moon3.jpg")
+        .map_err(|e| format!("could not open moon3.jpg: {}", e))?
+        .decode()
+        .map_err(|e| format!("could not decode moon3.jpg: {}", e))?
+        .into_rgb8();
     let img = Picture::new(image);
     let mat = Material::new(Reflection::Diffuse(), Texture::Img { img }, Vec3::default());
     let star_mat = Material{refl: Reflection::Diffuse(), tex: Texture::Solid { color: Vec3::new1(1.) }, emmision: Vec3::new1(2.)};
     let mut stars: Vec<Object> = vec![];
+    //fixed seed, the stars stay in place between renders
+    let mut rng = StdRng::seed_from_u64(0);
     for _ in 0..100{
-        let rand = Vec3::random();
+        let rand = Vec3::random(&mut rng);
         let pos = Vec3::new(rand.x*300., rand.y*100.+300., rand.z.abs()*100.);
         let star = Object::Sphere { pos, rad: 0.1, mat: star_mat.clone() };
         stars.push(star);
@@ -25,13 +34,15 @@ pub fn lalaland()-> Uniforms{
     let mut input = Uniforms {
         sample_count: 200, 
         bounce_count: 5,
-        offset: WIDTH as f32/1000.,
+        offset: DEFAULT_WIDTH as f32/1000.,
         cam: Camera::new(
             &Vec3::new(0., -20., 3.3),
             &Vec3::new(0., 0., 3.),
             45.,
             &Vec3::up(),
-            0.
+            0.,
+            DEFAULT_WIDTH,
+            DEFAULT_HEIGHT
         ),
         objects: vec![
             Object::Sphere { pos: Vec3::new(0., 0., -120.), rad: 120., mat: Material::default() },
@@ -43,24 +54,27 @@ pub fn lalaland()-> Uniforms{
             let col2 = Vec3::new(0.018, 0.0157, 0.2039);
             let value = v.dot(&Vec3::up()).max(0.);
             col1.lerp(&col2, value)
-        } )
+        } ),
+        ..Default::default()
     };
-    let b = Object::BoundBox { min: Vec3::new(-300., 200., 0.), max: Vec3::new(300., 400., 100.), inside: stars };
+    let b = Object::new_bound_box(stars);
     input.objects.push(b);
-    input
+    Ok(input)
 }
 
 pub fn cylinder_test()->Uniforms{
     let mut input = Uniforms {
         sample_count: 500,
         bounce_count: 5,
-        offset: WIDTH as f32/1000.,
+        offset: DEFAULT_WIDTH as f32/1000.,
         cam: Camera::new(
             &Vec3::new(0., -5., 3.3),
             &Vec3::new(0., 0., 3.),
             90.,
             &Vec3::up(),
-            0.
+            0.,
+            DEFAULT_WIDTH,
+            DEFAULT_HEIGHT
         ),
         objects: vec![
             Object::Plane { pos: Vec3::default(), normal: Vec3::up(), mat: Material::default() },
@@ -71,20 +85,20 @@ pub fn cylinder_test()->Uniforms{
             let col2 = Vec3::new(0.0275, 0.0078, 0.098);
             col1.lerp(&col2, v.dot(&Vec3::up()).max(0.))
         }),
+        ..Default::default()
     };
     //let mat = Material {refl: Reflection::Glass { reflective: 1.5 }, tex: Texture::Solid { color: Vec3::new1(1.) }, emmision: Vec3::new1(0.)};
     let cyl = abstract_object::new_cylinder(&Vec3::new(3., 0.,  1.5), &Vec3::new(0., 0., 3.), 32, 2.,true, Material::default());
-    let cyl_bound = Object::BoundBox { min: Vec3::new(0.99, -1.99, 0.), max: Vec3::new(5., 2., 3.), inside: cyl };
+    let cyl_bound = Object::new_bound_box(cyl);
     input.objects.push(cyl_bound);
     let pyr = abstract_object::new_pyramid(&Vec3::new(0., 0., 2.), 3., &(Vec3::up()*4.), 45., Material::default());
-    let pyr_bound = Object::BoundBox { min: Vec3::new(-2., -2.3, 0.), max: Vec3::new(2., 2., 4.), inside: pyr };
+    let pyr_bound = Object::new_bound_box(pyr);
     input.objects.push(pyr_bound);
     input
 }
 
 pub fn scene3()->Uniforms{
     let mut boxes: Vec<Object> = vec![];
-    let mut _rand = rand::thread_rng();
     for x in (-2..=2).step_by(2){
         for y in (-2..=2).step_by(2){
             let height = 2.;
@@ -94,17 +108,18 @@ pub fn scene3()->Uniforms{
         }
     }
     //boxes.append(&mut abstract_object::new_box(&Vec3::new(0., 0., 1.), &Vec3::new(0., 0., 2.), 2., Material::default()));
-    let boxes_bound = Object::BoundBox { min: Vec3::new1(-10.), max: Vec3::new1(10.), inside: boxes };
+    let boxes_bound = Object::new_bound_box(boxes);
     Uniforms{
         sample_count: 100,
         bounce_count: 10,
-        offset: WIDTH as f32/1000.,
-        cam: Camera::new(&Vec3::new(0., -5., 4.), &Vec3::default(), 90., &Vec3::up(), 0.1),
+        offset: DEFAULT_WIDTH as f32/1000.,
+        cam: Camera::new(&Vec3::new(0., -5., 4.), &Vec3::default(), 90., &Vec3::up(), 0.1, DEFAULT_WIDTH, DEFAULT_HEIGHT),
         objects: vec![
             Object::Plane { pos: Vec3::default(), normal: Vec3::up(), mat: Material{refl: Reflection::Diffuse(), tex: Texture::Solid { color: Vec3::new(0.9, 0.9, 0.) }, emmision: Vec3::new1(0.)} },
             boxes_bound
         ],
-        env_shader: Uniforms::get_env_shader()
+        env_shader: Uniforms::get_env_shader(),
+        ..Default::default()
     }
 } 
 
@@ -113,11 +128,12 @@ pub fn fast()->Uniforms{
         sample_count: 20,
         bounce_count: 5,
         offset: 0.,
-        cam: Camera::new(&Vec3::default(), &Vec3::back(), 45., &Vec3::up(), 0.),
+        cam: Camera::new(&Vec3::default(), &Vec3::back(), 45., &Vec3::up(), 0., DEFAULT_WIDTH, DEFAULT_HEIGHT),
         objects: vec![
             Object::Plane { pos: Vec3::up()*-1., normal: Vec3::up(), mat: Material::default() },
         ],
-        env_shader: Uniforms::get_env_shader()
+        env_shader: Uniforms::get_env_shader(),
+        ..Default::default()
     }
 }
 
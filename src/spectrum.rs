@@ -0,0 +1,198 @@
+use std::sync::OnceLock;
+use crate::Vec3;
+
+//the visible range the spectral mode samples, in nm
+pub const MIN_WAVELENGTH: f32 = 380.;
+pub const MAX_WAVELENGTH: f32 = 720.;
+
+//smits' spectra of the rgb primaries and their mixtures, in 10 equal bins over the visible range,
+//https://www.cs.utah.edu/~bes/papers/color/paper.pdf
+const WHITE: [f32; 10] = [1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000];
+const CYAN: [f32; 10] = [0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000];
+const MAGENTA: [f32; 10] = [1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959];
+const YELLOW: [f32; 10] = [0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840];
+const RED: [f32; 10] = [0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149];
+const GREEN: [f32; 10] = [0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025];
+const BLUE: [f32; 10] = [1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496];
+
+//the wavelengths the primaries of the rgb values are taken to be at, for quantities that are measured, not mixed
+const RGB_WAVELENGTHS: [f32; 3] = [650., 550., 450.];
+
+//the value of a smits spectrum at lambda
+fn bin(spectrum: &[f32; 10], lambda: f32) -> f32 {
+    let t = (lambda - MIN_WAVELENGTH) / (MAX_WAVELENGTH - MIN_WAVELENGTH);
+    spectrum[((t * 10.) as usize).min(9)]
+}
+
+//a smooth spectrum with the rgb color, evaluated at lambda
+fn smits(rgb: &Vec3, lambda: f32) -> f32 {
+    let (r, g, b) = (rgb.x, rgb.y, rgb.z);
+    let s = |spectrum: &[f32; 10]| bin(spectrum, lambda);
+    //the smallest channel is white, the gap to the middle one a mixture and the rest a primary
+    let v = if r <= g && r <= b {
+        r * s(&WHITE) + if g <= b { (g - r) * s(&CYAN) + (b - g) * s(&BLUE) } else { (b - r) * s(&CYAN) + (g - b) * s(&GREEN) }
+    } else if g <= r && g <= b {
+        g * s(&WHITE) + if r <= b { (r - g) * s(&MAGENTA) + (b - r) * s(&BLUE) } else { (b - g) * s(&MAGENTA) + (r - b) * s(&RED) }
+    } else {
+        b * s(&WHITE) + if r <= g { (r - b) * s(&YELLOW) + (g - r) * s(&GREEN) } else { (g - b) * s(&YELLOW) + (r - g) * s(&RED) }
+    };
+    v.max(0.)
+}
+
+//a gaussian with a different width on either side of its peak
+fn lobe(lambda: f32, mu: f32, left: f32, right: f32) -> f32 {
+    let t = (lambda - mu) / if lambda < mu { left } else { right };
+    (-0.5 * t * t).exp()
+}
+
+//the cie 1931 color matching functions, the multi-lobe fit of https://jcgt.org/published/0002/02/01/
+pub fn xyz_matching(lambda: f32) -> Vec3 {
+    Vec3::new(
+        1.056 * lobe(lambda, 599.8, 37.9, 31.0) + 0.362 * lobe(lambda, 442.0, 16.0, 26.7) - 0.065 * lobe(lambda, 501.1, 20.4, 26.2),
+        0.821 * lobe(lambda, 568.8, 46.9, 40.5) + 0.286 * lobe(lambda, 530.9, 16.3, 31.1),
+        1.217 * lobe(lambda, 437.0, 11.8, 36.0) + 0.681 * lobe(lambda, 459.0, 26.0, 13.8),
+    )
+}
+
+//linear srgb with the d65 white point
+fn xyz_to_rgb(xyz: &Vec3) -> Vec3 {
+    Vec3::new(
+        3.2404542 * xyz.x - 1.5371385 * xyz.y - 0.4985314 * xyz.z,
+        -0.969266 * xyz.x + 1.8760108 * xyz.y + 0.0415560 * xyz.z,
+        0.0556434 * xyz.x - 0.2040259 * xyz.y + 1.0572252 * xyz.z,
+    )
+}
+
+//the rgb of the spectrum that is 1 everywhere, dividing by it keeps white surfaces under white light white
+fn white_balance() -> &'static Vec3 {
+    static WHITE_RGB: OnceLock<Vec3> = OnceLock::new();
+    WHITE_RGB.get_or_init(|| {
+        let xyz = (MIN_WAVELENGTH as u32..MAX_WAVELENGTH as u32).fold(Vec3::default(), |sum, l| sum + xyz_matching(l as f32 + 0.5));
+        xyz_to_rgb(&xyz)
+    })
+}
+
+//the wavelengths one path of the spectral mode carries, the x, y and z of its colors are the values at them
+#[derive(Clone, Debug, PartialEq)]
+pub struct Wavelengths {
+    pub lambda: [f32; 3],
+    //set once a dispersive surface ended the second and third wavelength, the first carries the path alone after it
+    pub secondary_terminated: bool,
+}
+
+impl Wavelengths {
+    //u is uniform in 0-1, the first wavelength is uniform over the visible range and the others
+    //are spaced evenly after it, so every wavelength has the same pdf
+    pub fn sample(u: f32) -> Self {
+        let range = MAX_WAVELENGTH - MIN_WAVELENGTH;
+        let lambda = [0., 1., 2.].map(|i| MIN_WAVELENGTH + ((u + i / 3.) % 1.) * range);
+        Self { lambda, secondary_terminated: false }
+    }
+
+    //ends the second and third wavelength where every wavelength goes another way, the first one then stands
+    //in for all three, the path is multiplied with the returned weight, which makes up for the others only once
+    pub fn terminate_secondary(&mut self) -> Vec3 {
+        let first = if self.secondary_terminated { 1. } else { 3. };
+        self.secondary_terminated = true;
+        Vec3::new(first, 0., 0.)
+    }
+
+    //a smooth spectrum with the rgb color, for reflectances and emission
+    pub fn upsample(&self, rgb: &Vec3) -> Vec3 {
+        let [a, b, c] = self.lambda.map(|l| smits(rgb, l));
+        Vec3::new(a, b, c)
+    }
+
+    //linear interpolation between values measured at a red, a green and a blue wavelength, like indices of refraction
+    pub fn interpolate(&self, rgb: &Vec3) -> Vec3 {
+        let [a, b, c] = self.lambda.map(|l| {
+            let [red, green, blue] = RGB_WAVELENGTHS;
+            if l >= green {
+                rgb.y + (rgb.x - rgb.y) * ((l - green) / (red - green)).min(1.)
+            } else {
+                rgb.y + (rgb.z - rgb.y) * ((green - l) / (green - blue)).min(1.)
+            }
+        });
+        Vec3::new(a, b, c)
+    }
+
+    //the rgb color of radiance carried at the wavelengths, averaged over many paths it converges to the color of the spectrum
+    pub fn to_rgb(&self, values: &Vec3) -> Vec3 {
+        //every wavelength is one of three samples taken with the pdf 1 / range
+        let scale = (MAX_WAVELENGTH - MIN_WAVELENGTH) / 3.;
+        let xyz = xyz_matching(self.lambda[0]) * values.x + xyz_matching(self.lambda[1]) * values.y + xyz_matching(self.lambda[2]) * values.z;
+        let white = white_balance();
+        let rgb = xyz_to_rgb(&(xyz * scale));
+        Vec3::new(rgb.x / white.x, rgb.y / white.y, rgb.z / white.z)
+    }
+}
+
+//index of refraction depending on the wavelength
+#[derive(Clone, Debug, PartialEq)]
+pub enum Ior {
+    //n = a + b / lambda^2, lambda in micrometers
+    Cauchy { a: f32, b: f32 },
+    //n^2 = 1 + sum of b * lambda^2 / (lambda^2 - c), lambda in micrometers
+    Sellmeier { b: [f32; 3], c: [f32; 3] },
+}
+
+impl Ior {
+    //the wavelength of the sodium d line, where the ior of glass is usually given
+    pub const D_LINE: f32 = 587.6;
+    pub const NAMES: [&'static str; 3] = ["bk7", "sf11", "diamond"];
+
+    //borosilicate crown glass, dense flint glass and diamond
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "bk7" => Some(Self::Sellmeier { b: [1.0396122, 0.23179235, 1.0104694], c: [0.0060006985, 0.020017914, 103.56065] }),
+            "sf11" => Some(Self::Sellmeier { b: [1.737597, 0.31374735, 1.8987811], c: [0.013188707, 0.062306814, 155.2363] }),
+            "diamond" => Some(Self::Sellmeier { b: [4.3356, 0.3306, 0.], c: [0.0112360, 0.0306250, 0.] }),
+            _ => None,
+        }
+    }
+
+    //lambda in nm
+    pub fn at(&self, lambda: f32) -> f32 {
+        let l = lambda / 1000.;
+        let l2 = l * l;
+        match self {
+            Self::Cauchy { a, b } => a + b / l2,
+            Self::Sellmeier { b, c } => (1. + (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum::<f32>()).sqrt(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //the rgb of many paths that carry the upsampled color
+    fn round_trip(rgb: &Vec3) -> Vec3 {
+        let n = 3000;
+        let sum = (0..n).fold(Vec3::default(), |sum, i| {
+            let w = Wavelengths::sample((i as f32 + 0.5) / n as f32);
+            sum + w.to_rgb(&w.upsample(rgb))
+        });
+        sum / n as f32
+    }
+
+    #[test]
+    fn upsampled_colors_come_back() {
+        let white = round_trip(&Vec3::new1(1.));
+        assert!((&white - Vec3::new1(1.)).length() < 0.01, "white came back as {:?}", white);
+        for rgb in [Vec3::new(0.8, 0.2, 0.1), Vec3::new(0.1, 0.6, 0.3), Vec3::new(0.2, 0.3, 0.9), Vec3::new1(0.5)] {
+            let back = round_trip(&rgb);
+            assert!((&back - &rgb).length() < 0.1, "{:?} came back as {:?}", rgb, back);
+        }
+    }
+
+    #[test]
+    fn glass_presets_match_their_catalog_ior() {
+        for (name, n) in [("bk7", 1.5168), ("sf11", 1.7847), ("diamond", 2.4168)] {
+            let ior = Ior::preset(name).unwrap();
+            assert!((ior.at(Ior::D_LINE) - n).abs() < 2e-3, "{} has ior {} instead of {}", name, ior.at(Ior::D_LINE), n);
+            //blue light is bent more than red
+            assert!(ior.at(450.) > ior.at(650.), "{} does not disperse", name);
+        }
+    }
+}
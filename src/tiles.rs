@@ -0,0 +1,143 @@
+use std::str::FromStr;
+
+//a rectangle of pixels that is rendered by one thread, x1 and y1 are exclusive
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tile {
+    pub x0: usize,
+    pub y0: usize,
+    pub x1: usize,
+    pub y1: usize,
+}
+
+impl Tile {
+    pub fn width(&self) -> usize {
+        self.x1 - self.x0
+    }
+
+    pub fn height(&self) -> usize {
+        self.y1 - self.y0
+    }
+
+    pub fn pixel_count(&self) -> usize {
+        self.width() * self.height()
+    }
+
+    //the pixels row by row
+    pub fn pixels(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (self.y0..self.y1).flat_map(move |y| (self.x0..self.x1).map(move |x| (x, y)))
+    }
+}
+
+//the order the tiles are handed to the threads, and so the order they show up in snapshots
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum TileOrder {
+    //row by row from the top
+    Scanline,
+    //from the center outwards, the middle of the image is visible first
+    #[default]
+    Spiral,
+    //along a hilbert curve, neighbouring tiles are rendered close in time
+    Hilbert,
+}
+
+impl TileOrder {
+    pub const NAMES: [&'static str; 3] = ["scanline", "spiral", "hilbert"];
+}
+
+impl FromStr for TileOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "scanline" => Ok(Self::Scanline),
+            "spiral" => Ok(Self::Spiral),
+            "hilbert" => Ok(Self::Hilbert),
+            _ => Err(format!("unknown tile order '{}', available orders: {}", s, Self::NAMES.join(", "))),
+        }
+    }
+}
+
+//position of (x, y) along the hilbert curve that fills a size*size grid, size is a power of two
+//https://en.wikipedia.org/wiki/Hilbert_curve
+fn hilbert_index(size: usize, mut x: usize, mut y: usize) -> usize {
+    let mut d = 0;
+    let mut s = size / 2;
+    while s > 0 {
+        let rx = (x & s > 0) as usize;
+        let ry = (y & s > 0) as usize;
+        d += s * s * ((3 * rx) ^ ry);
+        //rotate the quadrant so the curve continues
+        if ry == 0 {
+            if rx == 1 {
+                x = size - 1 - x;
+                y = size - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    d
+}
+
+//splits the image into tiles of size*size pixels, the ones at the right and bottom edge may be smaller
+pub fn tiles(width: usize, height: usize, size: usize, order: TileOrder) -> Vec<Tile> {
+    assert!(size > 0, "tile size must not be zero at tiles");
+    let (nx, ny) = (width.div_ceil(size), height.div_ceil(size));
+    let mut grid: Vec<(usize, usize)> = (0..ny).flat_map(|ty| (0..nx).map(move |tx| (tx, ty))).collect();
+    match order {
+        TileOrder::Scanline => {}
+        TileOrder::Spiral => {
+            //ring around the center first, the angle inside the ring after
+            let (cx, cy) = ((nx as f32 - 1.) / 2., (ny as f32 - 1.) / 2.);
+            let key = |&(tx, ty): &(usize, usize)| {
+                let (dx, dy) = (tx as f32 - cx, ty as f32 - cy);
+                (dx.abs().max(dy.abs()), dy.atan2(dx))
+            };
+            grid.sort_by(|a, b| key(a).partial_cmp(&key(b)).unwrap());
+        }
+        TileOrder::Hilbert => {
+            let size = nx.max(ny).next_power_of_two();
+            grid.sort_by_key(|&(tx, ty)| hilbert_index(size, tx, ty));
+        }
+    }
+    grid.into_iter().map(|(tx, ty)| Tile {
+        x0: tx * size,
+        y0: ty * size,
+        x1: ((tx + 1) * size).min(width),
+        y1: ((ty + 1) * size).min(height),
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_order_covers_each_pixel_once() {
+        let (width, height) = (100, 70);
+        for order in [TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert] {
+            let mut covered = vec![0; width * height];
+            for tile in tiles(width, height, 16, order) {
+                for (x, y) in tile.pixels() {
+                    covered[y * width + x] += 1;
+                }
+            }
+            assert!(covered.iter().all(|c| *c == 1), "{:?} does not cover every pixel once", order);
+        }
+    }
+
+    #[test]
+    fn spiral_starts_at_the_center_and_hilbert_steps_to_neighbours() {
+        let spiral = tiles(90, 90, 10, TileOrder::Spiral);
+        assert_eq!(spiral[0], Tile { x0: 40, y0: 40, x1: 50, y1: 50 });
+        //every ring around the center is done before the next one
+        let ring = |t: &Tile| (t.x0 as i32 / 10 - 4).abs().max((t.y0 as i32 / 10 - 4).abs());
+        assert!(spiral.windows(2).all(|w| ring(&w[0]) <= ring(&w[1])));
+
+        let hilbert = tiles(80, 80, 10, TileOrder::Hilbert);
+        for w in hilbert.windows(2) {
+            let step = w[0].x0.abs_diff(w[1].x0) + w[0].y0.abs_diff(w[1].y0);
+            assert_eq!(step, 10, "hilbert jumps from {:?} to {:?}", w[0], w[1]);
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use crate::{Vec3, bvh::Aabb, geometry::{HitInfo, Ray}};
+
+fn identity() -> Vec<Vec<f32>> {
+    vec![
+        vec![1., 0., 0.],
+        vec![0., 1., 0.],
+        vec![0., 0., 1.],
+    ]
+}
+
+fn mat_mul(a: &[Vec<f32>], b: &[Vec<f32>]) -> Vec<Vec<f32>> {
+    (0..3).map(|r| (0..3).map(|c| (0..3).map(|k| a[r][k] * b[k][c]).sum()).collect()).collect()
+}
+
+fn transpose(m: &[Vec<f32>]) -> Vec<Vec<f32>> {
+    (0..3).map(|r| (0..3).map(|c| m[c][r]).collect()).collect()
+}
+
+fn invert(m: &[Vec<f32>]) -> Vec<Vec<f32>> {
+    //adjugate divided by the determinant
+    let c0 = Vec3::new(m[0][0], m[1][0], m[2][0]);
+    let c1 = Vec3::new(m[0][1], m[1][1], m[2][1]);
+    let c2 = Vec3::new(m[0][2], m[1][2], m[2][2]);
+    let r0 = c1.cross(&c2);
+    let r1 = c2.cross(&c0);
+    let r2 = c0.cross(&c1);
+    let det = c0.dot(&r0);
+    assert!(det.abs() > 1e-12, "transform matrix is not invertible, is a scale zero?");
+    vec![
+        vec![r0.x / det, r0.y / det, r0.z / det],
+        vec![r1.x / det, r1.y / det, r1.z / det],
+        vec![r2.x / det, r2.y / det, r2.z / det],
+    ]
+}
+
+//affine transform: p' = mat * p + translation
+#[derive(Clone, Debug)]
+pub struct Transform {
+    mat: Vec<Vec<f32>>,
+    inv: Vec<Vec<f32>>,
+    translation: Vec3,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self { mat: identity(), inv: identity(), translation: Vec3::default() }
+    }
+}
+
+impl Transform {
+    pub fn new(mat: Vec<Vec<f32>>, translation: Vec3) -> Self {
+        let inv = invert(&mat);
+        Self { mat, inv, translation }
+    }
+
+    pub fn translate(offset: &Vec3) -> Self {
+        Self { translation: offset.clone(), ..Default::default() }
+    }
+
+    pub fn scale(factor: &Vec3) -> Self {
+        Self::new(vec![
+            vec![factor.x, 0., 0.],
+            vec![0., factor.y, 0.],
+            vec![0., 0., factor.z],
+        ], Vec3::default())
+    }
+
+    //the columns are the images of the base vectors, same rotation as Vec3::rot_x
+    pub fn rotate_x(degree: f32) -> Self {
+        Self::from_columns(&Vec3::side().rot_x(degree), &Vec3::back().rot_x(degree), &Vec3::up().rot_x(degree))
+    }
+
+    pub fn rotate_y(degree: f32) -> Self {
+        Self::from_columns(&Vec3::side().rot_y(degree), &Vec3::back().rot_y(degree), &Vec3::up().rot_y(degree))
+    }
+
+    pub fn rotate_z(degree: f32) -> Self {
+        Self::from_columns(&Vec3::side().rot_z(degree), &Vec3::back().rot_z(degree), &Vec3::up().rot_z(degree))
+    }
+
+    fn from_columns(x: &Vec3, y: &Vec3, z: &Vec3) -> Self {
+        Self::new(vec![
+            vec![x.x, y.x, z.x],
+            vec![x.y, y.y, z.y],
+            vec![x.z, y.z, z.z],
+        ], Vec3::default())
+    }
+
+    //applies self first and other after it
+    pub fn then(&self, other: &Self) -> Self {
+        Self {
+            mat: mat_mul(&other.mat, &self.mat),
+            inv: mat_mul(&self.inv, &other.inv),
+            translation: other.apply_point(&self.translation),
+        }
+    }
+
+    pub fn apply_point(&self, p: &Vec3) -> Vec3 {
+        p.mat_mult(&self.mat) + &self.translation
+    }
+
+    pub fn apply_vector(&self, v: &Vec3) -> Vec3 {
+        v.mat_mult(&self.mat)
+    }
+
+    //normals stay perpendicular to the surface only with the inverse transpose
+    pub fn apply_normal(&self, n: &Vec3) -> Vec3 {
+        n.mat_mult(&transpose(&self.inv)).normalize()
+    }
+
+    pub fn inverse_point(&self, p: &Vec3) -> Vec3 {
+        (p - &self.translation).mat_mult(&self.inv)
+    }
+
+    pub fn inverse_vector(&self, v: &Vec3) -> Vec3 {
+        v.mat_mult(&self.inv)
+    }
+
+    //the ray in the space of the transformed object, the direction is normalized again
+    pub fn inverse_ray(&self, ray: &Ray) -> Ray {
+        Ray::new(self.inverse_point(&ray.start), self.inverse_vector(&ray.dir).normalize())
+    }
+
+    //moves a hit found in object space back into the world, transformed emitters are not in the light list
+    pub fn apply_hit(&self, mut hit: HitInfo) -> HitInfo {
+        hit.p = self.apply_point(&hit.p);
+        hit.normal = self.apply_normal(&hit.normal);
+        hit.light = None;
+        hit
+    }
+
+    pub fn apply_bounds(&self, bounds: &Aabb) -> Aabb {
+        let Aabb { min, max } = bounds;
+        let corners: Vec<Vec3> = (0..8).map(|i| self.apply_point(&Vec3::new(
+            if i & 1 == 0 { min.x } else { max.x },
+            if i & 2 == 0 { min.y } else { max.y },
+            if i & 4 == 0 { min.z } else { max.z },
+        ))).collect();
+        Aabb::from_points(&corners)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use super::*;
+    use crate::{Object, geometry::Material};
+
+    fn close(a: &Vec3, b: &Vec3) -> bool {
+        (a - b).length() < 1e-5
+    }
+
+    #[test]
+    fn transforms_apply_in_order_and_invert() {
+        let t = Transform::scale(&Vec3::new(2., 1., 1.)).then(&Transform::rotate_z(90.)).then(&Transform::translate(&Vec3::new(1., 2., 3.)));
+        //x is doubled, turned onto y and then moved
+        assert!(close(&t.apply_point(&Vec3::new(1., 0., 0.)), &Vec3::new(1., 4., 3.)));
+        let p = Vec3::new(0.3, -1.2, 2.5);
+        assert!(close(&t.inverse_point(&t.apply_point(&p)), &p));
+        assert!(close(&t.inverse_vector(&t.apply_vector(&p)), &p));
+        //the normal of the plane x = -y stays perpendicular to it under the uneven scale
+        let (normal, along) = (Vec3::new(1., 1., 0.).normalize(), Vec3::new(1., -1., 0.));
+        assert!(t.apply_normal(&normal).dot(&t.apply_vector(&along)).abs() < 1e-5);
+    }
+
+    #[test]
+    fn instances_are_hit_where_their_transform_puts_them() {
+        let sphere = Arc::new(Object::Sphere { pos: Vec3::default(), rad: 1., mat: Material::default() });
+        let obj = Object::new_instance(&sphere, Transform::scale(&Vec3::new1(2.)).then(&Transform::translate(&Vec3::new(0., 10., 0.))));
+        let hit = obj.intersect(&Ray::new(Vec3::default(), Vec3::new(0., 1., 0.))).unwrap();
+        assert!(close(&hit.p, &Vec3::new(0., 8., 0.)) && close(&hit.normal, &Vec3::new(0., -1., 0.)), "hit at {:?} facing {:?}", hit.p, hit.normal);
+        assert!(obj.intersect(&Ray::new(Vec3::new(3., 0., 0.), Vec3::new(0., 1., 0.))).is_none());
+        let bounds = obj.bounds().unwrap();
+        assert!(close(&bounds.min, &Vec3::new(-2., 8., -2.)) && close(&bounds.max, &Vec3::new(2., 12., 2.)), "bounds {:?}", bounds);
+    }
+}
@@ -36,21 +36,20 @@ impl Vec3 {
         self.x * other.x + self.y * other.y + self.z * other.z
     }
     pub fn cross(&self, other: &Self) -> Self {
-        let sol = Self::new(
+        Self::new(
             self.y * other.z - self.z * other.y,
             self.z * other.x - self.x * other.z,
             self.x * other.y - self.y * other.x
-        ); 
-        sol
+        )
     }
     pub fn normalize(&self) -> Vec3 {
         self / self.length()
     }
     pub fn lerp(&self, other: &Self, value: f32) -> Self {
-        assert!(value >= 0.0 && value <= 1.0);
+        assert!((0.0..=1.0).contains(&value));
         self*(1.0-value) + other*value
     }
-    pub fn mat_mult(&self, mat: &Vec<Vec<f32>>) -> Self{
+    pub fn mat_mult(&self, mat: &[Vec<f32>]) -> Self{
         Self::new(
             self.x * mat[0][0] + self.y * mat[0][1] + self.z * mat[0][2],
             self.x * mat[1][0] + self.y * mat[1][1] + self.z * mat[1][2],
@@ -62,8 +61,8 @@ impl Vec3 {
         self.x * self.x + self.y * self.y + self.z * self.z
     }
 
-    pub fn random() -> Self {
-        let mut rng = rand::thread_rng();
+    //uniform direction
+    pub fn random(rng: &mut impl Rng) -> Self {
         let mut sol = Self::new(0., 0., 0.);
         for _ in 0..50 {
             let x = rng.gen_range(-1.0..=1.0);
@@ -107,6 +106,14 @@ impl Vec3 {
         ];
         self.mat_mult(&rot)
     }    
+    //two unit vectors perpendicular to self and to each other, self has to be normalized
+    pub fn orthonormal_basis(&self) -> (Vec3, Vec3) {
+        let helper = if self.x.abs() > 0.9 { Vec3::back() } else { Vec3::side() };
+        let a = self.cross(&helper).normalize();
+        let b = self.cross(&a);
+        (a, b)
+    }
+
     pub fn is_normalized(&self)->bool{
         let len = self.length();
         len > 0.998 && len < 1.002
@@ -115,29 +122,35 @@ impl Vec3 {
     pub fn calc_new_bases(base_z: &Self)->Vec<Vec<f32>>{
 
         if base_z.dot(&Vec3::up()) > 0.998{
-            return vec![
+            vec![
                 vec![1., 0., 0.],
                 vec![0., 1., 0.],
                 vec![0., 0., 1.],
-            ];
+            ]
         } else if base_z.dot(&Vec3::up()) < -0.998 {
-            return vec![
+            vec![
                 vec![1., 0., 0.],
                 vec![0., 1., 0.],
                 vec![0., 0., -1.],
-            ];
+            ]
         } else {
             let base_x = base_z.cross(&Vec3::up());
             let base_y = base_z.cross(&base_x);
-            return vec![
+            vec![
                 vec![base_x.x, base_y.x, base_z.x],
                 vec![base_x.y, base_y.y, base_z.y],
                 vec![base_x.z, base_y.z, base_z.z],
-            ];
+            ]
         }
 
     }
 }
+impl From<[f32; 3]> for Vec3 {
+    fn from(arr: [f32; 3]) -> Self {
+        Vec3::new(arr[0], arr[1], arr[2])
+    }
+}
+
 // add
 impl Add<&Vec3> for &Vec3{
     type Output = Vec3;
@@ -215,7 +228,7 @@ impl Sub for Vec3{
 impl Mul<&f32> for &Vec3{
     type Output = Vec3;
     fn mul(self, rhs: &f32) -> Self::Output {
-        Vec3::new(self.x*rhs, self.y*rhs, self.z*rhs)
+        self * *rhs
     }
 }
 
@@ -223,7 +236,7 @@ impl Mul<f32> for &Vec3{
     type Output = Vec3;
     
     fn mul(self, rhs: f32) -> Self::Output {
-        self * &rhs
+        Vec3::new(self.x*rhs, self.y*rhs, self.z*rhs)
     }
 }
 
@@ -238,7 +251,7 @@ impl Mul<f32> for Vec3{
     type Output = Vec3;
     
     fn mul(self, rhs: f32) -> Self::Output {
-        &self * &rhs
+        &self * rhs
     }
 }
 
@@ -314,7 +327,7 @@ impl Mul<&Vec3> for f32 {
 impl Div<&f32> for &Vec3{
     type Output = Vec3;
     fn div(self, rhs: &f32) -> Self::Output {
-        Vec3::new(self.x/rhs, self.y/rhs, self.z/rhs)
+        self / *rhs
     }
 }
 
@@ -322,7 +335,7 @@ impl Div<f32> for &Vec3{
     type Output = Vec3;
     
     fn div(self, rhs: f32) -> Self::Output {
-        self / &rhs
+        Vec3::new(self.x/rhs, self.y/rhs, self.z/rhs)
     }
 }
 
@@ -337,7 +350,7 @@ impl Div<f32> for Vec3{
     type Output = Vec3;
     
     fn div(self, rhs: f32) -> Self::Output {
-        &self / &rhs
+        &self / rhs
     }
 }
 